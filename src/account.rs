@@ -0,0 +1,177 @@
+//! Operations on a whole user account and all of its data.
+use std::time::Duration;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use tracing::{event, Level};
+
+use crate::database::daos::api_token_dao::ApiTokenDAO;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::daos::file_version_dao::FileVersionDAO;
+use crate::database::daos::session_dao::SessionDAO;
+use crate::database::daos::share_dao::ShareDAO;
+use crate::database::daos::syncstate_dao::SyncStateDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::daos::verification_token_dao::VerificationTokenDAO;
+use crate::database::entities::user::{User, UserUsage};
+use crate::jwt_utils::InvalidatedJWTStore;
+use crate::storage::storage_provider::StorageProvider;
+use crate::SETTINGS;
+
+const DUE_DELETIONS_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Revokes all refresh and access tokens of the user, so every device has to log in again.
+pub async fn logout_everywhere(
+    user_id: ObjectId,
+    invalidated_jwt_store: &InvalidatedJWTStore,
+) -> actix_web::Result<()> {
+    SessionDAO::revoke_all_for_user(user_id).await?;
+    invalidated_jwt_store.invalidate_all_for_user(user_id).await
+}
+
+/// Revokes all sessions of the user except the given one, e.g. after a password change on that device.
+pub async fn logout_other_sessions(
+    user_id: ObjectId,
+    current_session_id: Option<ObjectId>,
+    invalidated_jwt_store: &InvalidatedJWTStore,
+) -> actix_web::Result<()> {
+    for session in SessionDAO::get_active_for_user(user_id).await? {
+        if let Some(session_id) = session.id.filter(|id| Some(*id) != current_session_id) {
+            SessionDAO::revoke(session_id).await?;
+            invalidated_jwt_store
+                .invalidate_session(user_id, session_id)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn get_usage(user_id: ObjectId) -> actix_web::Result<UserUsage> {
+    let files = FileDAO::get_all_for_user(user_id).await?;
+    let file_versions = FileVersionDAO::get_all_for_user(user_id).await?;
+
+    Ok(UserUsage {
+        file_count: files.len() as u64,
+        directory_count: DirectoryDAO::count_for_user(user_id).await?,
+        used_bytes: files
+            .iter()
+            .map(|file| file.uuid.clone())
+            .chain(file_versions.iter().map(|version| version.uuid.clone()))
+            .map(StorageProvider::get_file_size)
+            .sum(),
+    })
+}
+
+/// Deletes the user together with all files, file versions, directories, shares and tokens of the user.
+pub async fn delete(
+    user: &User,
+    invalidated_jwt_store: &InvalidatedJWTStore,
+) -> actix_web::Result<()> {
+    let user_id = user
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("user id not found"))?;
+
+    logout_everywhere(user_id, invalidated_jwt_store).await?;
+
+    for file in FileDAO::get_all_for_user(user_id).await? {
+        if let Err(e) = StorageProvider::delete_file(file.uuid.clone()) {
+            // unfinished uploads may not have a blob yet
+            event!(
+                Level::WARN,
+                "could not delete blob of file {}: {}",
+                file.uuid,
+                e
+            );
+        }
+    }
+    for file_version in FileVersionDAO::get_all_for_user(user_id).await? {
+        if let Err(e) = StorageProvider::delete_file(file_version.uuid.clone()) {
+            event!(
+                Level::WARN,
+                "could not delete blob of file version {}: {}",
+                file_version.uuid,
+                e
+            );
+        }
+    }
+    FileDAO::delete_all_for_user(user_id).await?;
+    FileVersionDAO::delete_all_for_user(user_id).await?;
+    DirectoryDAO::delete_all_for_user(user_id).await?;
+    ShareDAO::delete_all_for_user(user_id).await?;
+    SyncStateDAO::delete_all_for_user(user_id).await?;
+    ApiTokenDAO::delete_all_for_user(user_id).await?;
+    VerificationTokenDAO::delete_all_for_user(user_id).await?;
+    SessionDAO::delete_all_for_user(user_id).await?;
+    UserDAO::delete(user).await?;
+
+    event!(Level::INFO, "deleted user {} and all of its data", user_id);
+    Ok(())
+}
+
+/// Deletes the account after the configured grace period, or immediately if there is none.
+/// During the grace period the user is logged out and cannot log in, until the account is restored.
+pub async fn request_deletion(
+    user: &mut User,
+    immediately: bool,
+    invalidated_jwt_store: &InvalidatedJWTStore,
+) -> actix_web::Result<()> {
+    let grace_period_days = SETTINGS.get().unwrap().account_deletion_grace_period_days;
+    if immediately || grace_period_days <= 0 {
+        return delete(user, invalidated_jwt_store).await;
+    }
+
+    let user_id = user
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("user id not found"))?;
+
+    user.deletion_date = Some(DateTime::from_millis(
+        DateTime::now().timestamp_millis()
+            + time::Duration::days(grace_period_days).whole_milliseconds() as i64,
+    ));
+    UserDAO::update(user).await?;
+    logout_everywhere(user_id, invalidated_jwt_store).await?;
+
+    event!(
+        Level::INFO,
+        "scheduled deletion of user {} for {:?}",
+        user_id,
+        user.deletion_date
+    );
+    Ok(())
+}
+
+pub async fn restore(user: &mut User) -> actix_web::Result<()> {
+    if user.deletion_date.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The account is not scheduled for deletion",
+        ));
+    }
+
+    user.deletion_date = None;
+    UserDAO::update(user).await?;
+    Ok(())
+}
+
+/// Periodically deletes the accounts whose grace period has ended.
+pub fn spawn_due_deletions(invalidated_jwt_store: InvalidatedJWTStore) {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(DUE_DELETIONS_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match UserDAO::get_due_for_deletion(DateTime::now()).await {
+                Ok(users) => {
+                    for user in users {
+                        if let Err(e) = delete(&user, &invalidated_jwt_store).await {
+                            event!(Level::ERROR, "Failed to delete user {:?}: {}", user.id, e);
+                        }
+                    }
+                }
+                Err(e) => event!(Level::WARN, "Failed to get users due for deletion: {}", e),
+            }
+        }
+    });
+}
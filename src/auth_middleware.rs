@@ -0,0 +1,211 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use actix_jwt_authc::{Authenticated, JWT};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use time::OffsetDateTime;
+
+use crate::database::daos::api_token_dao::ApiTokenDAO;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::api_token::{ApiTokenScope, API_TOKEN_PREFIX};
+use crate::database::entities::user::{Role, User};
+use crate::jwt_utils::{
+    get_jwt_ttl, get_unix_timestamp_millis, hash_opaque_token, Claims, InvalidatedJWTStore,
+    JwtKeyring,
+};
+
+const AUTHORIZATION_HEADER_PREFIX: &str = "Bearer ";
+
+/// Authenticates requests carrying a `Bearer` jwt, signed by any key of the [JwtKeyring],
+/// or a personal api token, which is only accepted for routes covered by its scopes.
+///
+/// Valid tokens are injected as [Authenticated] into the request, so handlers can still use
+/// the actix-jwt-authc extractor. Requests without token are passed on unauthenticated.
+#[derive(Clone)]
+pub struct AuthenticateMiddlewareFactory {
+    jwt_keyring: Arc<JwtKeyring>,
+    invalidated_jwt_store: InvalidatedJWTStore,
+}
+
+impl AuthenticateMiddlewareFactory {
+    pub fn new(
+        jwt_keyring: Arc<JwtKeyring>,
+        invalidated_jwt_store: InvalidatedJWTStore,
+    ) -> AuthenticateMiddlewareFactory {
+        AuthenticateMiddlewareFactory {
+            jwt_keyring,
+            invalidated_jwt_store,
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AuthenticateMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = AuthenticateMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticateMiddleware {
+            service: Rc::new(service),
+            jwt_keyring: self.jwt_keyring.clone(),
+            invalidated_jwt_store: self.invalidated_jwt_store.clone(),
+        }))
+    }
+}
+
+pub struct AuthenticateMiddleware<S> {
+    service: Rc<S>,
+    jwt_keyring: Arc<JwtKeyring>,
+    invalidated_jwt_store: InvalidatedJWTStore,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticateMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let jwt_keyring = self.jwt_keyring.clone();
+        let invalidated_jwt_store = self.invalidated_jwt_store.clone();
+
+        async move {
+            if let Some(jwt) = extract_bearer_jwt(&req) {
+                if invalidated_jwt_store.contains(&jwt) {
+                    return Err(actix_web::error::ErrorUnauthorized(
+                        "Invalidated session, the jwt was already invalidated",
+                    ));
+                }
+
+                let claims = if jwt.0.starts_with(API_TOKEN_PREFIX) {
+                    authenticate_api_token(&jwt, req.method(), req.path()).await?
+                } else {
+                    jwt_keyring.decode::<Claims>(jwt.0.as_str()).map_err(|e| {
+                        actix_web::error::ErrorUnauthorized(format!("Invalid session [{}]", e))
+                    })?
+                };
+
+                if invalidated_jwt_store.is_revoked(&claims) {
+                    return Err(actix_web::error::ErrorUnauthorized(
+                        "Invalidated session, the session has been logged out",
+                    ));
+                }
+
+                req.extensions_mut().insert(Authenticated { jwt, claims });
+            }
+
+            service.call(req).await
+        }
+        .boxed_local()
+    }
+}
+
+/// Checks a personal api token and its scopes and returns the claims of its user.
+async fn authenticate_api_token(
+    token: &JWT,
+    method: &Method,
+    path: &str,
+) -> actix_web::Result<Claims> {
+    let mut api_token =
+        ApiTokenDAO::get_by_token_hash(hash_opaque_token(token.0.as_str()).as_str())
+            .await?
+            .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid api token"))?;
+
+    if api_token.is_expired() {
+        return Err(actix_web::error::ErrorUnauthorized("Api token expired"));
+    }
+
+    match ApiTokenScope::required_for(method, path) {
+        Some(scope) if api_token.has_scope(scope) => {}
+        _ => {
+            return Err(actix_web::error::ErrorForbidden(
+                "Api token is missing the scope for this request",
+            ))
+        }
+    }
+
+    let user = UserDAO::get(api_token.user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid api token"))?;
+    if user.disabled {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "User account is disabled",
+        ));
+    }
+    let root_dir_id = user
+        .root_dir_id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("User has no root directory"))?;
+
+    ApiTokenDAO::register_usage(&mut api_token).await?;
+
+    let now = OffsetDateTime::now_utc();
+    let iat = now.unix_timestamp() as usize;
+    Ok(Claims {
+        exp: match api_token.expiration_date {
+            Some(expiration_date) => (expiration_date.timestamp_millis() / 1000) as usize,
+            None => iat + get_jwt_ttl().0.whole_seconds() as usize,
+        },
+        iat,
+        iat_ms: Some(get_unix_timestamp_millis(now)),
+        sub: api_token.user_id.to_string(),
+        thunder_root_dir_id: root_dir_id,
+        sid: None,
+    })
+}
+
+fn extract_bearer_jwt(req: &ServiceRequest) -> Option<JWT> {
+    let authorization_header = req.headers().get("Authorization")?.to_str().ok()?;
+    authorization_header
+        .strip_prefix(AUTHORIZATION_HEADER_PREFIX)
+        .map(|jwt| JWT(jwt.trim().to_string()))
+}
+
+/// Extracts an authenticated user with the admin role, other users are rejected with 403.
+pub struct AdminAuthenticated {
+    pub user: User,
+}
+
+impl FromRequest for AdminAuthenticated {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let authenticated = Authenticated::<Claims>::from_request(req, payload);
+
+        async move {
+            let authenticated = authenticated.await?;
+            let user = UserDAO::get_authenticated(&authenticated)
+                .await?
+                .ok_or_else(|| {
+                    actix_web::error::ErrorUnauthorized("User does not exist anymore")
+                })?;
+
+            if !matches!(user.role, Role::Admin) {
+                return Err(actix_web::error::ErrorForbidden(
+                    "This action requires the administrator role",
+                ));
+            }
+
+            Ok(AdminAuthenticated { user })
+        }
+        .boxed_local()
+    }
+}
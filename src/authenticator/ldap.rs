@@ -0,0 +1,292 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ldap3::{
+    dn_escape, ldap_escape, Ldap as LdapClient, LdapConnAsync, LdapConnSettings, Scope, SearchEntry,
+};
+use tracing::{event, Level};
+
+use crate::authenticator::Authenticator;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::user::{Role, User, UserLogin};
+use crate::jwt_utils::generate_opaque_token;
+use crate::settings::Ldap;
+
+const LDAP_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
+
+/// Binds as the user against a directory service and syncs the user attributes into the local database.
+pub struct LdapAuthenticator {
+    settings: &'static Ldap,
+}
+
+/// The attributes read from the directory entry of the user.
+struct LdapUser {
+    dn: String,
+    firstname: String,
+    lastname: String,
+    email: String,
+    is_admin: Option<bool>, // not set if no admin group is configured
+}
+
+impl LdapAuthenticator {
+    pub fn new(settings: &'static Ldap) -> LdapAuthenticator {
+        LdapAuthenticator { settings }
+    }
+
+    /// Returns the dn of the user, either from the template or by searching with the service account.
+    async fn find_user_dn(
+        &self,
+        ldap: &mut LdapClient,
+        username: &str,
+    ) -> actix_web::Result<Option<String>> {
+        if !self.settings.bind_dn_template.is_empty() {
+            return Ok(Some(get_user_dn(
+                self.settings.bind_dn_template.as_str(),
+                username,
+            )));
+        }
+
+        if !self.settings.bind_dn.is_empty() {
+            ldap.simple_bind(
+                self.settings.bind_dn.as_str(),
+                self.settings.bind_password.as_str(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorBadGateway)?
+            .success()
+            .map_err(actix_web::error::ErrorBadGateway)?;
+        }
+
+        let (entries, _) = ldap
+            .search(
+                self.settings.user_search_base.as_str(),
+                Scope::Subtree,
+                get_user_filter(self.settings.user_filter.as_str(), username).as_str(),
+                vec!["1.1"], // no attributes, only the dn is required
+            )
+            .await
+            .map_err(actix_web::error::ErrorBadGateway)?
+            .success()
+            .map_err(actix_web::error::ErrorBadGateway)?;
+
+        // an ambiguous filter must not log in a random one of the matching users
+        if entries.len() != 1 {
+            return Ok(None);
+        }
+        Ok(entries
+            .into_iter()
+            .next()
+            .map(|entry| SearchEntry::construct(entry).dn))
+    }
+
+    async fn read_user(&self, ldap: &mut LdapClient, dn: String) -> actix_web::Result<LdapUser> {
+        let (entries, _) = ldap
+            .search(
+                dn.as_str(),
+                Scope::Base,
+                "(objectClass=*)",
+                vec![
+                    self.settings.firstname_attribute.as_str(),
+                    self.settings.lastname_attribute.as_str(),
+                    self.settings.email_attribute.as_str(),
+                ],
+            )
+            .await
+            .map_err(actix_web::error::ErrorBadGateway)?
+            .success()
+            .map_err(actix_web::error::ErrorBadGateway)?;
+
+        let entry = SearchEntry::construct(entries.into_iter().next().ok_or_else(|| {
+            actix_web::error::ErrorBadGateway("directory entry of the user could not be read")
+        })?);
+        let get_attribute = |name: &str| {
+            entry
+                .attrs
+                .get(name)
+                .and_then(|values| values.first())
+                .cloned()
+        };
+
+        let is_admin = if self.settings.admin_group_dn.is_empty() {
+            None
+        } else {
+            let (group_entries, _) = ldap
+                .search(
+                    self.settings.admin_group_dn.as_str(),
+                    Scope::Base,
+                    format!(
+                        "({}={})",
+                        self.settings.group_member_attribute,
+                        ldap_escape(dn.as_str())
+                    )
+                    .as_str(),
+                    vec!["1.1"],
+                )
+                .await
+                .map_err(actix_web::error::ErrorBadGateway)?
+                .success()
+                .map_err(actix_web::error::ErrorBadGateway)?;
+            Some(!group_entries.is_empty())
+        };
+
+        Ok(LdapUser {
+            firstname: get_attribute(self.settings.firstname_attribute.as_str())
+                .unwrap_or_default(),
+            lastname: get_attribute(self.settings.lastname_attribute.as_str()).unwrap_or_default(),
+            email: get_attribute(self.settings.email_attribute.as_str()).ok_or_else(|| {
+                actix_web::error::ErrorBadGateway("directory entry of the user has no email")
+            })?,
+            dn,
+            is_admin,
+        })
+    }
+
+    async fn bind_and_read_user(
+        &self,
+        ldap: &mut LdapClient,
+        login: &UserLogin,
+        password: &str,
+    ) -> actix_web::Result<Option<LdapUser>> {
+        let dn = match self.find_user_dn(ldap, login.email.as_str()).await? {
+            Some(dn) => dn,
+            None => return Ok(None),
+        };
+
+        let bind_result = ldap
+            .simple_bind(dn.as_str(), password)
+            .await
+            .map_err(actix_web::error::ErrorBadGateway)?;
+        if bind_result.rc == LDAP_INVALID_CREDENTIALS {
+            return Ok(None);
+        }
+        bind_result
+            .success()
+            .map_err(actix_web::error::ErrorBadGateway)?;
+
+        Ok(Some(self.read_user(ldap, dn).await?))
+    }
+}
+
+#[async_trait(?Send)]
+impl Authenticator for LdapAuthenticator {
+    fn name(&self) -> &'static str {
+        "ldap"
+    }
+
+    async fn authenticate(&self, login: &UserLogin) -> actix_web::Result<Option<User>> {
+        // directories accept binds without password as anonymous binds, so they have to be rejected here
+        let password = match &login.password {
+            Some(password) if !password.is_empty() => password,
+            _ => return Ok(None),
+        };
+
+        let (conn, mut ldap) = LdapConnAsync::with_settings(
+            LdapConnSettings::new()
+                .set_conn_timeout(LDAP_CONNECT_TIMEOUT)
+                .set_starttls(self.settings.starttls),
+            self.settings.url.as_str(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?;
+        ldap3::drive!(conn);
+
+        let ldap_user = self
+            .bind_and_read_user(&mut ldap, login, password.as_str())
+            .await;
+        if let Err(e) = ldap.unbind().await {
+            event!(Level::WARN, "ldap unbind failed: {}", e);
+        }
+
+        match ldap_user? {
+            Some(ldap_user) => Ok(Some(sync_user(ldap_user).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Creates or updates the local user of the directory entry.
+/// Local accounts are never linked by their email, as whoever controls the `mail` attribute of an
+/// entry could take them over, so all linked accounts have been created by the directory.
+async fn sync_user(ldap_user: LdapUser) -> actix_web::Result<User> {
+    let mut user = match UserDAO::get_by_ldap_dn(ldap_user.dn.as_str()).await? {
+        Some(user) => user,
+        None => match UserDAO::get_by_email(ldap_user.email.as_str()).await? {
+            Some(user) => {
+                event!(
+                    Level::WARN,
+                    "directory entry {} has the email of the unlinked user {:?}",
+                    ldap_user.dn,
+                    user.id
+                );
+                return Err(actix_web::error::ErrorConflict(
+                    "A different account with this email already exists",
+                ));
+            }
+            None => {
+                // the account can only be used with the directory, as nobody knows this password
+                let mut user = User::new(
+                    ldap_user.firstname.clone(),
+                    ldap_user.lastname.clone(),
+                    ldap_user.email.clone(),
+                    User::hash_password(generate_opaque_token().as_str())?,
+                );
+                UserDAO::insert_with_root_dir(&mut user).await?;
+                user
+            }
+        },
+    };
+
+    if user.email != ldap_user.email && UserDAO::exists(&ldap_user.email).await? {
+        return Err(actix_web::error::ErrorConflict(
+            "A different account with this email already exists",
+        ));
+    }
+
+    user.firstname = ldap_user.firstname;
+    user.lastname = ldap_user.lastname;
+    user.email = ldap_user.email;
+    user.email_verified = true;
+    user.ldap_dn = Some(ldap_user.dn);
+    match ldap_user.is_admin {
+        Some(true) => user.role = Role::Admin,
+        Some(false) => user.role = Role::BaseUser,
+        None => {}
+    }
+    UserDAO::update(&user).await?;
+
+    Ok(user)
+}
+
+fn get_user_dn(bind_dn_template: &str, username: &str) -> String {
+    bind_dn_template.replace("{username}", dn_escape(username).as_ref())
+}
+
+fn get_user_filter(user_filter: &str, username: &str) -> String {
+    user_filter.replace("{username}", ldap_escape(username).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_username() {
+        assert_eq!(
+            get_user_dn("uid={username},ou=people,dc=example,dc=org", "ada"),
+            "uid=ada,ou=people,dc=example,dc=org"
+        );
+        assert_eq!(
+            get_user_dn(
+                "uid={username},ou=people,dc=example,dc=org",
+                "ada,ou=admins"
+            ),
+            "uid=ada\\2cou\\3dadmins,ou=people,dc=example,dc=org"
+        );
+        assert_eq!(
+            get_user_filter("(|(uid={username})(mail={username}))", "*)(uid=*"),
+            "(|(uid=\\2a\\29\\28uid=\\2a)(mail=\\2a\\29\\28uid=\\2a))"
+        );
+    }
+}
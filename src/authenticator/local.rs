@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+
+use crate::authenticator::Authenticator;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::user::{User, UserLogin};
+
+/// Checks the password hash of users stored in the local database.
+/// Federated users are left to their directory or identity provider.
+pub struct LocalAuthenticator {}
+
+#[async_trait(?Send)]
+impl Authenticator for LocalAuthenticator {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn authenticate(&self, login: &UserLogin) -> actix_web::Result<Option<User>> {
+        let mut user = match UserDAO::get_by_email(login.email.as_str()).await? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+
+        if !can_log_in_locally(&user, login.pw_hash.as_str()) {
+            return Ok(None);
+        }
+
+        if user.has_legacy_pw_hash() {
+            // transparently upgrade records that still hold the plain client hash
+            user.pw_hash = User::hash_password(login.pw_hash.as_str())?;
+            UserDAO::update(&user).await?;
+        }
+
+        Ok(Some(user))
+    }
+}
+
+/// A stored hash of a federated user must not work, e.g. while the directory is unavailable,
+/// otherwise disabling the account or changing the password there would be bypassed.
+fn can_log_in_locally(user: &User, pw_hash: &str) -> bool {
+    !user.is_federated() && user.verify_password(pw_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PW_HASH: &str = "1bc464c87c470882de2453b9978c4fa61dd680c30617b68c5ac1d4052ed39aef";
+
+    fn get_user() -> User {
+        User::new(
+            "".to_string(),
+            "".to_string(),
+            "".to_string(),
+            User::hash_password(PW_HASH).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_can_log_in_locally() {
+        let user = get_user();
+        assert!(can_log_in_locally(&user, PW_HASH));
+        assert!(!can_log_in_locally(
+            &user,
+            "4fcdced7b0bdb6d4861c458c74bf0b8ace258c5d4fcdced7b0bdb6d4861c458c"
+        ));
+
+        let mut directory_user = get_user();
+        directory_user.ldap_dn = Some("uid=jdoe,ou=people,dc=example,dc=org".to_string());
+        assert!(!can_log_in_locally(&directory_user, PW_HASH));
+
+        let mut provider_user = get_user();
+        provider_user.oidc_subject = Some("248289761001".to_string());
+        assert!(!can_log_in_locally(&provider_user, PW_HASH));
+    }
+}
@@ -0,0 +1,60 @@
+pub mod ldap;
+pub mod local;
+
+use async_trait::async_trait;
+use tracing::{event, Level};
+
+use crate::authenticator::ldap::LdapAuthenticator;
+use crate::authenticator::local::LocalAuthenticator;
+use crate::database::entities::user::{User, UserLogin};
+use crate::SETTINGS;
+
+/// A source of user credentials, like the local user database or a directory service.
+/// Logins are handled on the worker thread of the request, so the futures do not have to be `Send`.
+#[async_trait(?Send)]
+pub trait Authenticator {
+    fn name(&self) -> &'static str;
+
+    /// Returns the authenticated user, or `None` if the backend does not know the user
+    /// or the credentials are wrong, so the next backend can be asked.
+    async fn authenticate(&self, login: &UserLogin) -> actix_web::Result<Option<User>>;
+}
+
+/// Returns the enabled backends in the order they are asked, the local user database always comes last.
+fn get_authenticators() -> Vec<Box<dyn Authenticator>> {
+    let settings = SETTINGS.get().unwrap();
+    let mut authenticators: Vec<Box<dyn Authenticator>> = Vec::new();
+
+    if settings.ldap.enabled {
+        authenticators.push(Box::new(LdapAuthenticator::new(&settings.ldap)));
+    }
+    authenticators.push(Box::new(LocalAuthenticator {}));
+
+    authenticators
+}
+
+/// Asks all backends for the user, an unavailable backend does not prevent logins with the others.
+pub async fn authenticate(login: &UserLogin) -> actix_web::Result<Option<User>> {
+    for authenticator in get_authenticators() {
+        match authenticator.authenticate(login).await {
+            Ok(Some(user)) => {
+                event!(
+                    Level::INFO,
+                    "user {:?} authenticated by {}",
+                    user.id,
+                    authenticator.name()
+                );
+                return Ok(Some(user));
+            }
+            Ok(None) => {}
+            Err(e) => event!(
+                Level::ERROR,
+                "authentication backend {} failed: {}",
+                authenticator.name(),
+                e
+            ),
+        }
+    }
+
+    Ok(None)
+}
@@ -1,11 +1,21 @@
+use crate::controller::invitation::create_invitation;
 use crate::controller::utils::extract_object_id_or_die;
 use crate::database::daos::dao::DAO;
+use crate::database::daos::invitation_dao::InvitationDAO;
 use crate::database::daos::user_dao::UserDAO;
 use crate::database::entities::user::Role;
+use crate::jwt_utils::JwtKeyring;
+use crate::quota;
 use crate::SETTINGS;
 
 use clap::{Parser, Subcommand};
+use jsonwebtoken::Algorithm;
+use std::path::Path;
 use std::process::exit;
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+const RECOMPUTE_USAGE_PAGE_SIZE: u64 = 500;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -38,6 +48,60 @@ enum Commands {
         /// give a user (identified by id) the base user role
         #[arg(long, value_name = "user_id")]
         set_base_user_role: Option<String>,
+
+        /// disable the two-factor authentication of a user (identified by id)
+        #[arg(long, value_name = "user_id")]
+        reset_2fa: Option<String>,
+
+        /// recompute the used storage of a user (identified by id) from the stored files
+        #[arg(long, value_name = "user_id")]
+        recompute_usage: Option<String>,
+
+        /// recompute the used storage of all users from the stored files
+        #[arg(long)]
+        recompute_all_usages: bool,
+    },
+    /// Manage invitation codes for the registration
+    #[command(arg_required_else_help(true))]
+    Invitation {
+        /// lists all invitations
+        #[arg(short, long)]
+        list: bool,
+
+        /// create a new invitation code
+        #[arg(short, long)]
+        create: bool,
+
+        /// only allow the given email to register with the new code
+        #[arg(long, value_name = "email")]
+        email: Option<String>,
+
+        /// how often the new code can be used (default: unlimited)
+        #[arg(long, value_name = "count", value_parser = clap::value_parser!(u32).range(1..))]
+        max_uses: Option<u32>,
+
+        /// days until the new code expires (default: never)
+        #[arg(long, value_name = "days")]
+        valid_days: Option<i64>,
+
+        /// delete an invitation (identified by id)
+        #[arg(long, value_name = "invitation_id")]
+        delete: Option<String>,
+    },
+    /// Manage the jwt signing keyring
+    #[command(arg_required_else_help(true))]
+    Jwt {
+        /// lists the keys of the jwt keyring (newest key signs, older keys only verify)
+        #[arg(short, long)]
+        list: bool,
+
+        /// add a new active signing key, takes effect after restarting the server
+        #[arg(long)]
+        rotate_key: bool,
+
+        /// signing algorithm of the new key (HS512 or EdDSA)
+        #[arg(long, value_name = "algorithm", default_value = "HS512")]
+        algorithm: String,
     },
 }
 
@@ -57,6 +121,9 @@ pub async fn process() {
             list,
             set_administrator_role,
             set_base_user_role,
+            reset_2fa,
+            recompute_usage,
+            recompute_all_usages,
         }) => {
             run_server_after_cmd_execution = false;
 
@@ -72,6 +139,80 @@ pub async fn process() {
                     .await
                     .unwrap();
                 println!("successfully removed admin role from user");
+            } else if let Some(reset_2fa) = reset_2fa {
+                reset_user_totp(reset_2fa.clone()).await.unwrap();
+                println!("successfully reset two-factor authentication of user");
+            } else if let Some(recompute_usage) = recompute_usage {
+                let uid = extract_object_id_or_die(Some(recompute_usage)).unwrap();
+                let used_bytes = quota::recompute_used_bytes(uid).await.unwrap();
+                println!(
+                    "successfully recomputed usage of user: {} bytes",
+                    used_bytes
+                );
+            } else if *recompute_all_usages {
+                let count = recompute_all_used_bytes().await.unwrap();
+                println!("successfully recomputed usage of {} users", count);
+            }
+        }
+        Some(Commands::Invitation {
+            list,
+            create,
+            email,
+            max_uses,
+            valid_days,
+            delete,
+        }) => {
+            run_server_after_cmd_execution = false;
+
+            if *create {
+                let valid_until = valid_days.map(|days| {
+                    (OffsetDateTime::now_utc() + time::Duration::days(days)).unix_timestamp() * 1000
+                });
+                let (id, code) = create_invitation(email.clone(), *max_uses, valid_until, None)
+                    .await
+                    .unwrap();
+                println!("successfully created invitation {}, code: {}", id, code);
+            } else if *list {
+                for invitation in InvitationDAO::get_all().await.unwrap() {
+                    let info = invitation.get_info();
+                    println!(
+                        "{}	email: {:?}	uses: {}/{:?}	usable: {}	expires: {:?}",
+                        info.id,
+                        info.email,
+                        info.use_count,
+                        info.max_uses,
+                        info.usable,
+                        invitation.expiration_date
+                    );
+                }
+            } else if let Some(delete) = delete {
+                delete_invitation(delete.clone()).await.unwrap();
+                println!("successfully deleted invitation");
+            }
+        }
+        Some(Commands::Jwt {
+            list,
+            rotate_key,
+            algorithm,
+        }) => {
+            run_server_after_cmd_execution = false;
+            let keyring_path = Path::new(settings.jwt_keyring_path.as_str());
+
+            if *rotate_key {
+                let algorithm =
+                    Algorithm::from_str(algorithm.as_str()).expect("unknown jwt signing algorithm");
+                let stored_key = JwtKeyring::add_key(keyring_path, algorithm).unwrap();
+                println!(
+                    "successfully added new signing key {} ({:?})",
+                    stored_key.kid, stored_key.algorithm
+                );
+            } else if *list {
+                for stored_key in JwtKeyring::read_stored_keys(keyring_path).unwrap() {
+                    println!(
+                        "{}\t{:?}\tcreated {}",
+                        stored_key.kid, stored_key.algorithm, stored_key.creation_date
+                    );
+                }
             }
         }
         None => {}
@@ -93,3 +234,45 @@ pub async fn update_user_role(uid: String, role: Role) -> actix_web::Result<()>
 
     Ok(())
 }
+
+pub async fn reset_user_totp(uid: String) -> actix_web::Result<()> {
+    let uid = extract_object_id_or_die(Some(&uid))?;
+    let user = UserDAO::get(uid).await?;
+
+    if let Some(mut user) = user {
+        user.reset_totp();
+        UserDAO::update(&user).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn recompute_all_used_bytes() -> actix_web::Result<u64> {
+    let mut page = 0;
+    let mut count = 0;
+
+    loop {
+        let (users, _) = UserDAO::search(None, page, RECOMPUTE_USAGE_PAGE_SIZE).await?;
+        for user in &users {
+            if let Some(user_id) = user.id {
+                quota::recompute_used_bytes(user_id).await?;
+                count += 1;
+            }
+        }
+
+        if (users.len() as u64) < RECOMPUTE_USAGE_PAGE_SIZE {
+            return Ok(count);
+        }
+        page += 1;
+    }
+}
+
+pub async fn delete_invitation(invitation_id: String) -> actix_web::Result<()> {
+    let invitation_id = extract_object_id_or_die(Some(&invitation_id))?;
+
+    if let Some(invitation) = InvitationDAO::get(invitation_id).await? {
+        InvitationDAO::delete(&invitation).await?;
+    }
+
+    Ok(())
+}
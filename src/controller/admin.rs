@@ -0,0 +1,206 @@
+use actix_web::web::{Data, Json};
+use actix_web::{web, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+use tracing::{event, Level};
+
+use crate::account;
+use crate::auth_middleware::AdminAuthenticated;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::login_throttle_dao::LoginThrottleDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::login_throttle::{
+    LoginThrottle, LoginThrottleDelete, LoginThrottleInfo,
+};
+use crate::database::entities::user::{
+    AccountDeleteResponse, User, UserDelete, UserId, UserInfo, UserQuotaUpdate, UserRoleUpdate,
+    UserSearch, UserSearchResponse,
+};
+use crate::jwt_utils::InvalidatedJWTStore;
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 500;
+
+pub async fn get_users(
+    _admin: AdminAuthenticated,
+    search_data: web::Query<UserSearch>,
+) -> actix_web::Result<HttpResponse> {
+    let page = search_data.page.unwrap_or(0);
+    let page_size = search_data
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let (users, total) = UserDAO::search(search_data.search.as_deref(), page, page_size).await?;
+
+    Ok(HttpResponse::Ok().json(UserSearchResponse {
+        users: users.iter().map(User::get_info).collect::<Vec<UserInfo>>(),
+        total,
+        page,
+        page_size,
+    }))
+}
+
+pub async fn get_user(
+    _admin: AdminAuthenticated,
+    user_data: web::Query<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(get_user_by_id(&user_data).await?.get_info()))
+}
+
+pub async fn get_user_usage(
+    _admin: AdminAuthenticated,
+    user_data: web::Query<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    get_user_by_id(&user_data).await?;
+
+    Ok(HttpResponse::Ok().json(account::get_usage(user_data.id).await?))
+}
+
+pub async fn update_user_role(
+    admin: AdminAuthenticated,
+    role_data: Json<UserRoleUpdate>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_other_user(&admin, role_data.id)?;
+    let mut user = get_user_by_id(&UserId { id: role_data.id }).await?;
+
+    user.role = role_data.role.clone();
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+/// Sets the storage quota of the user, without a value the configured default applies again.
+pub async fn update_user_quota(
+    _admin: AdminAuthenticated,
+    quota_data: Json<UserQuotaUpdate>,
+) -> actix_web::Result<HttpResponse> {
+    if matches!(quota_data.quota_bytes, Some(quota_bytes) if quota_bytes < 0) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The quota cannot be negative",
+        ));
+    }
+    let mut user = get_user_by_id(&UserId { id: quota_data.id }).await?;
+
+    user.quota_bytes = quota_data.quota_bytes;
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+pub async fn disable_user(
+    admin: AdminAuthenticated,
+    user_data: Json<UserId>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_other_user(&admin, user_data.id)?;
+    let mut user = get_user_by_id(&user_data).await?;
+
+    user.disabled = true;
+    UserDAO::update(&user).await?;
+    account::logout_everywhere(user_data.id, &invalidated_jwt_store).await?;
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+pub async fn enable_user(
+    _admin: AdminAuthenticated,
+    user_data: Json<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_user_by_id(&user_data).await?;
+
+    user.disabled = false;
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+pub async fn logout_user(
+    _admin: AdminAuthenticated,
+    user_data: Json<UserId>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    get_user_by_id(&user_data).await?;
+    account::logout_everywhere(user_data.id, &invalidated_jwt_store).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn delete_user(
+    admin: AdminAuthenticated,
+    delete_data: web::Query<UserDelete>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_other_user(&admin, delete_data.id)?;
+    let mut user = get_user_by_id(&UserId { id: delete_data.id }).await?;
+
+    event!(
+        Level::INFO,
+        "admin {:?} deletes user {}",
+        admin.user.id,
+        delete_data.id
+    );
+    account::request_deletion(&mut user, delete_data.immediately, &invalidated_jwt_store).await?;
+
+    Ok(HttpResponse::Ok().json(AccountDeleteResponse {
+        deletion_date_ts: user.deletion_date.map(|date| date.timestamp_millis()),
+    }))
+}
+
+pub async fn restore_user(
+    _admin: AdminAuthenticated,
+    user_data: Json<UserId>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_user_by_id(&user_data).await?;
+
+    account::restore(&mut user).await?;
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+/// Returns the accounts and ips, that are locked after too many failed logins or registrations.
+pub async fn get_lockouts(_admin: AdminAuthenticated) -> actix_web::Result<HttpResponse> {
+    let lockouts: Vec<LoginThrottleInfo> = LoginThrottleDAO::get_all_locked()
+        .await?
+        .iter()
+        .filter_map(LoginThrottle::get_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(lockouts))
+}
+
+/// Lifts a lockout and forgets the failed attempts.
+pub async fn delete_lockout(
+    admin: AdminAuthenticated,
+    lockout_data: web::Query<LoginThrottleDelete>,
+) -> actix_web::Result<HttpResponse> {
+    let lockout = LoginThrottleDAO::get(lockout_data.id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Requested lockout could not be found"))?;
+
+    event!(
+        Level::INFO,
+        "admin {:?} lifts the lockout of {} {}",
+        admin.user.id,
+        lockout.kind.as_ref(),
+        lockout.key
+    );
+    LoginThrottleDAO::delete(&lockout).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn get_user_by_id(user_data: &UserId) -> actix_web::Result<User> {
+    UserDAO::get(user_data.id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Requested user could not be found"))
+}
+
+/// Admins must not lock themselves out, at least one other admin has to do it.
+fn ensure_other_user(admin: &AdminAuthenticated, user_id: ObjectId) -> actix_web::Result<()> {
+    if admin.user.id == Some(user_id) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "This action cannot be applied to your own account",
+        ));
+    }
+    Ok(())
+}
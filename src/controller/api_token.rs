@@ -0,0 +1,70 @@
+use actix_jwt_authc::Authenticated;
+use actix_web::web::Json;
+use actix_web::{web, HttpResponse};
+
+use crate::controller::utils::ensure_verified_email;
+use crate::database::daos::api_token_dao::ApiTokenDAO;
+use crate::database::daos::dao::DAO;
+use crate::database::entities::api_token::{
+    ApiToken, ApiTokenCreate, ApiTokenCreateResponse, ApiTokenDelete, ApiTokenInfo,
+    API_TOKEN_PREFIX,
+};
+use crate::jwt_utils::{extract_user_oid, generate_opaque_token, hash_opaque_token};
+use crate::Claims;
+
+pub async fn create(
+    _authenticated: Authenticated<Claims>,
+    create_token_data: Json<ApiTokenCreate>,
+) -> actix_web::Result<HttpResponse> {
+    ensure_verified_email(&_authenticated).await?;
+
+    if create_token_data.name.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Token name cannot be empty",
+        ));
+    }
+    if create_token_data.scopes.is_empty() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "A token requires at least one scope",
+        ));
+    }
+
+    let token = format!("{}{}", API_TOKEN_PREFIX, generate_opaque_token());
+    let mut api_token = ApiToken::new(
+        extract_user_oid(&_authenticated),
+        create_token_data.name.to_string(),
+        hash_opaque_token(token.as_str()),
+        create_token_data.scopes.clone(),
+        create_token_data.valid_until,
+    );
+    let id = ApiTokenDAO::insert(&mut api_token).await?;
+
+    Ok(HttpResponse::Ok().json(ApiTokenCreateResponse { id, token }))
+}
+
+pub async fn get_all(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
+    let api_tokens: Vec<ApiTokenInfo> =
+        ApiTokenDAO::get_all_for_user(extract_user_oid(&_authenticated))
+            .await?
+            .iter()
+            .map(ApiToken::get_info)
+            .collect();
+
+    Ok(HttpResponse::Ok().json(api_tokens))
+}
+
+pub async fn delete(
+    _authenticated: Authenticated<Claims>,
+    delete_token_data: web::Query<ApiTokenDelete>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(api_token) =
+        ApiTokenDAO::get_with_user(delete_token_data.id, extract_user_oid(&_authenticated)).await?
+    {
+        ApiTokenDAO::delete(&api_token).await?;
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    Err(actix_web::error::ErrorBadRequest(
+        "Requested token could not be found",
+    ))
+}
@@ -11,14 +11,17 @@ use tracing::{event, Level};
 use crate::controller::utils::{
     extract_object_id, extract_object_id_or_die, get_archive_file_stream_http_response,
 };
+use crate::copy;
 use crate::database::daos::dao::DAO;
 use crate::database::daos::directory_dao::DirectoryDAO;
 use crate::database::entities::directory::{
-    Directory, DirectoryDelete, DirectoryGet, DirectoryGetResponse, DirectoryPatch, DirectoryPost,
+    Directory, DirectoryAncestor, DirectoryAncestorsGet, DirectoryCopy, DirectoryDelete,
+    DirectoryGet, DirectoryGetResponse, DirectoryPatch, DirectoryPost,
     GetDirectoryArchiveQueryParams,
 };
 use crate::jwt_utils::extract_user_oid;
 use crate::storage::storage_provider::StorageProvider;
+use crate::trash;
 use crate::Claims;
 
 pub async fn create(
@@ -46,6 +49,8 @@ pub async fn create(
         name: dir_post_data.name.to_owned().to_string(),
         creation_date: DateTime::now(),
         child_ids: vec![],
+        deletion_date: None,
+        trashed_with: None,
     };
 
     let dir_detail = DirectoryDAO::insert(&mut dir).await?;
@@ -115,11 +120,34 @@ pub async fn delete(
         actix_web::error::ErrorInternalServerError("Directory could not be found")
     })?;
 
-    DirectoryDAO::delete(&dir).await?;
+    trash::trash_directory(&dir).await?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+/// Copies the directory with its subtree on the server, the copy is renamed to "name (1)" on a
+/// name conflict.
+pub async fn copy(
+    _authenticated: Authenticated<Claims>,
+    copy_data: Json<DirectoryCopy>,
+) -> actix_web::Result<HttpResponse> {
+    let user_id = extract_user_oid(&_authenticated);
+    let dir = DirectoryDAO::get_with_user(extract_object_id_or_die(Some(&copy_data.id))?, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Directory not found"))?;
+
+    let target_id = extract_object_id(
+        copy_data.target_directory.as_ref(),
+        _authenticated.claims.thunder_root_dir_id,
+    )?;
+    let target = DirectoryDAO::get_with_user(target_id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Target directory not found"))?;
+
+    let copy = copy::copy_directory(&dir, &target).await?;
+    Ok(HttpResponse::Ok().json(copy.id))
+}
+
 pub async fn get(
     _authenticated: Authenticated<Claims>,
     dir_get_data: web::Query<DirectoryGet>,
@@ -135,7 +163,7 @@ pub async fn get(
     match dir {
         Some(dir) => Ok(HttpResponse::Ok().json(DirectoryGetResponse {
             dirs: DirectoryDAO::get_all_with_parent_id_for_response(dir.id).await?,
-            files: dir.get_files().await,
+            files: dir.get_finished_files().await,
         })),
         _ => Err(actix_web::error::ErrorInternalServerError(
             "Could not get requested directory",
@@ -143,6 +171,34 @@ pub async fn get(
     }
 }
 
+/// Returns the breadcrumb of the directory, starting with the root and ending with the directory.
+pub async fn get_ancestors(
+    _authenticated: Authenticated<Claims>,
+    query_params: web::Query<DirectoryAncestorsGet>,
+) -> actix_web::Result<HttpResponse> {
+    let dir = DirectoryDAO::get_with_user(
+        extract_object_id_or_die(Some(&query_params.id))?,
+        extract_user_oid(&_authenticated),
+    )
+    .await?
+    .ok_or_else(|| actix_web::error::ErrorBadRequest("Directory not found"))?;
+
+    let mut ancestors = DirectoryDAO::get_ancestors(&dir).await?;
+    ancestors.reverse();
+    ancestors.push(dir);
+
+    let breadcrumb: Vec<DirectoryAncestor> = ancestors
+        .into_iter()
+        .filter_map(|dir| {
+            Some(DirectoryAncestor {
+                id: dir.id?,
+                name: dir.name,
+            })
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(breadcrumb))
+}
+
 pub async fn get_directory_archive_stream(
     _authenticated: Authenticated<Claims>,
     query_params: web::Query<GetDirectoryArchiveQueryParams>,
@@ -3,24 +3,33 @@ use std::str::FromStr;
 
 use actix_jwt_authc::Authenticated;
 use actix_multipart::Multipart;
+use actix_web::http::StatusCode;
 use actix_web::web::Json;
 use actix_web::{web, HttpRequest, HttpResponse};
 use futures_util::TryStreamExt;
 use mongodb::bson::oid::ObjectId;
 use mongodb::bson::{DateTime, Uuid};
+use sha2::{Digest, Sha256};
 
 use crate::archive::ArchiveMethod;
-use crate::controller::utils::get_archive_file_stream_http_response;
+use crate::controller::utils::{extract_object_id, get_archive_file_stream_http_response};
+use crate::copy;
 use crate::database::daos::dao::DAO;
 use crate::database::daos::directory_dao::DirectoryDAO;
 use crate::database::daos::file_dao::FileDAO;
 use crate::database::daos::syncstate_dao::SyncStateDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::directory::Directory;
 use crate::database::entities::file::{
-    File, FilePatch, GetSingleQueryParams, MultiUploadQueryParams,
+    File, FileCopy, FilePatch, GetSingleQueryParams, MultiUploadQueryParams,
+    UploadConflictStrategy, UploadOutcome, UploadResult,
 };
 use crate::database::entities::syncstate::{SyncState, SyncStateAction, SyncStateType};
 use crate::jwt_utils::extract_user_oid;
+use crate::quota;
 use crate::storage::storage_provider::StorageProvider;
+use crate::trash;
+use crate::versioning;
 use crate::Claims;
 
 pub async fn get_single(
@@ -58,72 +67,239 @@ pub async fn multi_upload(
     _authenticated: Authenticated<Claims>,
     request: HttpRequest,
     query_params: web::Query<MultiUploadQueryParams>,
-    mut payload: Multipart,
+    payload: Multipart,
 ) -> actix_web::Result<HttpResponse> {
     let connection = request.connection_info().clone();
     let _host = connection.peer_addr().unwrap_or("unknown host");
-    let mut uploaded_files: Vec<File> = Vec::new();
     let user_id = extract_user_oid(&_authenticated);
 
     if let Ok(parent_id) = ObjectId::from_str(query_params.directory.as_str()) {
         let dir = DirectoryDAO::get_with_user(parent_id, user_id).await?;
         if let Some(dir) = dir {
-            while let Some(mut field) = payload.try_next().await? {
-                match field.name() {
-                    "file" => {
-                        // A multipart/form-data stream has to contain `content_disposition`
-                        let content_disposition = field.content_disposition();
+            let uploaded_files =
+                upload_into_directory(&dir, query_params.conflict, payload).await?;
+            return Ok(HttpResponse::Ok().json(uploaded_files));
+        }
+
+        return Err(actix_web::error::ErrorBadRequest("Directory not found"));
+    }
+
+    return Err(actix_web::error::ErrorBadRequest(
+        "Query field directory is not parseable",
+    ));
+}
+
+/// Stores the `file` fields of the multipart payload in the directory of their owner.
+/// Files exceeding the storage quota are reported as rejected, the other files are stored anyway.
+pub async fn upload_into_directory(
+    dir: &Directory,
+    conflict: UploadConflictStrategy,
+    mut payload: Multipart,
+) -> actix_web::Result<Vec<UploadResult>> {
+    let mut uploaded_files: Vec<UploadResult> = Vec::new();
+    let user_id = dir.user_id;
+    let user = UserDAO::get(user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?;
+    let quota_bytes = user.get_quota_bytes();
+    // only used to abort early, the quota is enforced when the bytes get reserved
+    let mut remaining_bytes = quota_bytes.map(|quota_bytes| quota_bytes - user.used_bytes);
 
-                        let filename = content_disposition
-                            .get_filename()
-                            .map_or_else(|| Uuid::new().to_string(), sanitize_filename::sanitize);
+    // set by an `mtime` field (timestamp with milliseconds), applies to the next file
+    let mut modification_date: Option<DateTime> = None;
 
-                        if dir.has_file_with_name(&filename).await {
+    while let Some(mut field) = payload.try_next().await? {
+        match field.name() {
+            "mtime" => {
+                let mut value = Vec::new();
+                while let Some(chunk) = field.try_next().await? {
+                    value.extend_from_slice(&chunk);
+                }
+                let mtime = String::from_utf8_lossy(&value)
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| {
+                        actix_web::error::ErrorBadRequest(
+                            "Field mtime has to be a timestamp with milliseconds",
+                        )
+                    })?;
+                modification_date = Some(DateTime::from_millis(mtime));
+            }
+            "file" => {
+                let modification_date = modification_date.take();
+
+                // A multipart/form-data stream has to contain `content_disposition`
+                let content_disposition = field.content_disposition();
+
+                let filename = content_disposition
+                    .get_filename()
+                    .map_or_else(|| Uuid::new().to_string(), sanitize_filename::sanitize);
+
+                let mut name = filename.clone();
+                let mut outcome = UploadOutcome::Created;
+                let mut replaced_file: Option<File> = None;
+                if let Some(existing_file) = dir.get_file_with_name(&filename).await {
+                    match conflict {
+                        UploadConflictStrategy::Rename => {
+                            name = dir.get_free_file_name(&filename).await;
+                            outcome = UploadOutcome::Renamed;
+                        }
+                        UploadConflictStrategy::Overwrite | UploadConflictStrategy::Version
+                            if existing_file.finished =>
+                        {
+                            outcome = match conflict {
+                                UploadConflictStrategy::Version => UploadOutcome::Versioned,
+                                _ => UploadOutcome::Overwritten,
+                            };
+                            replaced_file = Some(existing_file);
+                        }
+                        // unfinished resumable uploads cannot be replaced
+                        _ => {
+                            uploaded_files.push(UploadResult {
+                                name: filename,
+                                outcome: UploadOutcome::Skipped,
+                                file: None,
+                            });
                             continue;
                         }
+                    }
+                }
+
+                // an overwritten blob gives its bytes back, a versioned one is kept
+                let freed_bytes = match (&replaced_file, outcome) {
+                    (Some(replaced_file), UploadOutcome::Overwritten) => {
+                        StorageProvider::get_file_size(replaced_file.uuid.clone())
+                    }
+                    _ => 0,
+                };
+
+                let mut file = File {
+                    id: None,
+                    parent_id: dir.id.unwrap(),
+                    user_id,
+                    uuid: Uuid::new().to_string(),
+                    hash: "".to_string(),
+                    size: 0,
+                    mime: field.content_type().to_string(),
+                    name,
+                    finished: true,
+                    upload_length: None,
+                    upload_expiration_date: None,
+                    upload_lock: None,
+                    upload_locked_until: None,
+                    creation_date: DateTime::now(),
+                    modification_date,
+                    deletion_date: None,
+                    trashed_with: None,
+                };
+
+                // File::create is a blocking operation
+                let mut storage_file =
+                    StorageProvider::create_file_handle(file.uuid.clone()).await?;
 
-                        let mut file = File {
-                            id: None,
-                            parent_id: dir.id.unwrap(),
-                            user_id,
-                            uuid: Uuid::new().to_string(),
-                            hash: "".to_string(),
-                            mime: field.content_type().to_string(),
+                // Field in turn is stream of *Bytes* object
+                let mut hasher = Sha256::new();
+                let mut written_bytes: u64 = 0;
+                let mut exceeds_quota = false;
+                while let Some(chunk) = field.try_next().await? {
+                    written_bytes += chunk.len() as u64;
+                    if matches!(remaining_bytes, Some(remaining_bytes) if written_bytes as i64 > remaining_bytes + freed_bytes as i64)
+                    {
+                        exceeds_quota = true;
+                        break;
+                    }
+
+                    // filesystem operations are blocking, may we have to use threadpool
+                    (storage_file, hasher) = web::block(move || {
+                        hasher.update(&chunk);
+                        storage_file
+                            .write_all(&chunk)
+                            .map(|_| (storage_file, hasher))
+                    })
+                    .await??;
+                }
+                if exceeds_quota {
+                    StorageProvider::delete_file(file.uuid.clone())?;
+                    // the rest of the field has to be read, before the next files can be stored
+                    while field.try_next().await?.is_some() {}
+                    uploaded_files.push(UploadResult {
+                        name: filename,
+                        outcome: UploadOutcome::Rejected,
+                        file: None,
+                    });
+                    continue;
+                }
+                file.hash = hasher
+                    .finalize()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                file.size = written_bytes as i64;
+
+                if let Err(e) =
+                    quota::replace(user_id, freed_bytes, written_bytes, quota_bytes).await
+                {
+                    StorageProvider::delete_file(file.uuid.clone())?;
+                    // e.g. a parallel upload has used the remaining quota in the meantime
+                    if e.as_response_error().status_code() == StatusCode::PAYLOAD_TOO_LARGE {
+                        uploaded_files.push(UploadResult {
                             name: filename,
-                            finished: true,
-                            creation_date: DateTime::now(),
-                        };
-
-                        // File::create is a blocking operation
-                        let mut storage_file =
-                            StorageProvider::create_file_handle(file.uuid.clone()).await?;
-
-                        // Field in turn is stream of *Bytes* object
-                        while let Some(chunk) = field.try_next().await? {
-                            // filesystem operations are blocking, may we have to use threadpool
-                            storage_file = web::block(move || {
-                                storage_file.write_all(&chunk).map(|_| storage_file)
-                            })
-                            .await??;
-                        }
+                            outcome: UploadOutcome::Rejected,
+                            file: None,
+                        });
+                        continue;
+                    }
+                    return Err(e);
+                }
+                remaining_bytes = remaining_bytes.map(|remaining_bytes| {
+                    remaining_bytes - written_bytes as i64 + freed_bytes as i64
+                });
 
+                match replaced_file {
+                    Some(replaced_file) => {
+                        file = replace_file_content(replaced_file, file, outcome).await?;
+                    }
+                    None => {
                         // Save VirtualFile as DirFile to db
                         FileDAO::insert(&mut file).await?;
-                        uploaded_files.push(file);
                     }
-                    _ => {}
                 }
+                uploaded_files.push(UploadResult {
+                    name: filename,
+                    outcome,
+                    file: Some(file),
+                });
             }
-
-            return Ok(HttpResponse::Ok().json(uploaded_files));
+            _ => {}
         }
+    }
 
-        return Err(actix_web::error::ErrorBadRequest("Directory not found"));
+    Ok(uploaded_files)
+}
+
+/// Points the existing file to the newly uploaded blob. The previous blob is kept as a version
+/// or deleted, its bytes have already been booked by the caller.
+async fn replace_file_content(
+    mut replaced_file: File,
+    uploaded_file: File,
+    outcome: UploadOutcome,
+) -> actix_web::Result<File> {
+    let previous_file = replaced_file.clone();
+    replaced_file.uuid = uploaded_file.uuid;
+    replaced_file.hash = uploaded_file.hash;
+    replaced_file.size = uploaded_file.size;
+    replaced_file.mime = uploaded_file.mime;
+    replaced_file.modification_date = uploaded_file.modification_date;
+    FileDAO::update(&replaced_file).await?;
+
+    if outcome == UploadOutcome::Versioned {
+        versioning::keep_version(&previous_file).await?;
+    } else {
+        StorageProvider::delete_file(previous_file.uuid)?;
     }
+    versioning::insert_update_sync_state(&replaced_file).await?;
 
-    return Err(actix_web::error::ErrorBadRequest(
-        "Query field directory is not parseable",
-    ));
+    Ok(replaced_file)
 }
 
 pub async fn update(
@@ -211,6 +387,27 @@ pub async fn update(
     return Err(actix_web::error::ErrorBadRequest("File not found"));
 }
 
+/// Copies the file on the server, the copy is renamed to "name (1).ext" on a name conflict.
+pub async fn copy(
+    _authenticated: Authenticated<Claims>,
+    copy_data: Json<FileCopy>,
+) -> actix_web::Result<HttpResponse> {
+    let user_id = extract_user_oid(&_authenticated);
+    let file = FileDAO::get_file_by_uuid_for_user(&copy_data.uuid, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("File not found"))?;
+
+    let target_id = extract_object_id(
+        copy_data.target_directory.as_ref(),
+        _authenticated.claims.thunder_root_dir_id,
+    )?;
+    let target = DirectoryDAO::get_with_user(target_id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Target directory not found"))?;
+
+    Ok(HttpResponse::Ok().json(copy::copy_file(&file, &target).await?))
+}
+
 pub async fn delete(
     _authenticated: Authenticated<Claims>,
     query_params: web::Query<GetSingleQueryParams>,
@@ -219,8 +416,7 @@ pub async fn delete(
         FileDAO::get_file_by_uuid_for_user(&query_params.uuid, extract_user_oid(&_authenticated))
             .await?
     {
-        StorageProvider::delete_file(file.uuid.clone())?;
-        FileDAO::delete(&file).await?;
+        trash::trash_file(&file).await?;
 
         return Ok(HttpResponse::Ok().finish());
     }
@@ -0,0 +1,89 @@
+use actix_jwt_authc::Authenticated;
+use actix_web::web::Json;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::daos::file_version_dao::FileVersionDAO;
+use crate::database::entities::file::File;
+use crate::database::entities::file_version::{
+    FileVersion, FileVersionId, FileVersionInfo, FileVersionsQueryParams,
+};
+use crate::jwt_utils::extract_user_oid;
+use crate::storage::storage_provider::StorageProvider;
+use crate::versioning;
+use crate::Claims;
+
+/// Lists the kept versions of the file, the most recently replaced first.
+pub async fn get_all(
+    _authenticated: Authenticated<Claims>,
+    query_params: web::Query<FileVersionsQueryParams>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(file) =
+        FileDAO::get_file_by_uuid_for_user(&query_params.uuid, extract_user_oid(&_authenticated))
+            .await?
+    {
+        let file_versions: Vec<FileVersionInfo> =
+            FileVersionDAO::get_all_for_file(file.id.unwrap())
+                .await?
+                .iter()
+                .filter_map(FileVersion::get_info)
+                .collect();
+
+        return Ok(HttpResponse::Ok().json(file_versions));
+    }
+
+    Err(actix_web::error::ErrorBadRequest("File not found"))
+}
+
+pub async fn download(
+    _authenticated: Authenticated<Claims>,
+    query_params: web::Query<FileVersionId>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let (file, file_version) = get_with_file(&_authenticated, &query_params).await?;
+
+    // the version is served with the current name of the file
+    let version_file = File {
+        uuid: file_version.uuid,
+        mime: file_version.mime,
+        ..file
+    };
+    Ok(StorageProvider::get_named_file(&version_file)?.into_response(&req))
+}
+
+/// Makes the version the current content of the file, the replaced content becomes a version.
+pub async fn restore(
+    _authenticated: Authenticated<Claims>,
+    version_data: Json<FileVersionId>,
+) -> actix_web::Result<HttpResponse> {
+    let (file, file_version) = get_with_file(&_authenticated, &version_data).await?;
+
+    let file = versioning::restore(file, &file_version).await?;
+    Ok(HttpResponse::Ok().json(file))
+}
+
+pub async fn delete(
+    _authenticated: Authenticated<Claims>,
+    query_params: web::Query<FileVersionId>,
+) -> actix_web::Result<HttpResponse> {
+    let (_, file_version) = get_with_file(&_authenticated, &query_params).await?;
+
+    versioning::delete_version(&file_version).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn get_with_file(
+    authenticated: &Authenticated<Claims>,
+    version_id: &FileVersionId,
+) -> actix_web::Result<(File, FileVersion)> {
+    let user_id = extract_user_oid(authenticated);
+    let file_version = FileVersionDAO::get_with_user(version_id.id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("File version not found"))?;
+    let file = FileDAO::get_with_user(file_version.file_id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("File not found"))?;
+
+    Ok((file, file_version))
+}
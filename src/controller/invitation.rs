@@ -0,0 +1,75 @@
+use actix_web::web::Json;
+use actix_web::{web, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+
+use crate::auth_middleware::AdminAuthenticated;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::invitation_dao::InvitationDAO;
+use crate::database::entities::invitation::{
+    Invitation, InvitationCreate, InvitationCreateResponse, InvitationDelete, InvitationInfo,
+};
+use crate::jwt_utils::{generate_opaque_token, hash_opaque_token};
+
+pub async fn create(
+    admin: AdminAuthenticated,
+    create_invitation_data: Json<InvitationCreate>,
+) -> actix_web::Result<HttpResponse> {
+    if create_invitation_data.max_uses == Some(0) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "An invitation has to be usable at least once",
+        ));
+    }
+
+    let (id, code) = create_invitation(
+        create_invitation_data.email.clone(),
+        create_invitation_data.max_uses,
+        create_invitation_data.valid_until,
+        admin.user.id,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(InvitationCreateResponse { id, code }))
+}
+
+pub async fn get_all(_admin: AdminAuthenticated) -> actix_web::Result<HttpResponse> {
+    let invitation_infos: Vec<InvitationInfo> = InvitationDAO::get_all()
+        .await?
+        .iter()
+        .map(Invitation::get_info)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(invitation_infos))
+}
+
+pub async fn delete(
+    _admin: AdminAuthenticated,
+    delete_invitation_data: web::Query<InvitationDelete>,
+) -> actix_web::Result<HttpResponse> {
+    if let Some(invitation) = InvitationDAO::get(delete_invitation_data.id).await? {
+        return Ok(HttpResponse::Ok().json(InvitationDAO::delete(&invitation).await?));
+    }
+
+    Err(actix_web::error::ErrorBadRequest(
+        "Requested invitation could not be found",
+    ))
+}
+
+/// Stores a new invitation and returns its id and the plain code.
+pub async fn create_invitation(
+    email: Option<String>,
+    max_uses: Option<u32>,
+    valid_until: Option<i64>,
+    created_by: Option<ObjectId>,
+) -> actix_web::Result<(ObjectId, String)> {
+    let code = generate_opaque_token();
+    let mut invitation = Invitation::new(
+        hash_opaque_token(code.as_str()),
+        email,
+        max_uses,
+        valid_until,
+        created_by,
+    );
+    let id = InvitationDAO::insert(&mut invitation).await?;
+
+    Ok((id, code))
+}
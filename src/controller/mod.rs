@@ -1,6 +1,15 @@
+pub mod admin;
+pub mod api_token;
 pub mod directory;
 pub mod file;
+pub mod file_version;
+pub mod invitation;
+pub mod oidc;
+pub mod path;
+pub mod session;
 pub mod share;
 pub mod syncstate;
+pub mod trash;
+pub mod tus;
 pub mod user;
 pub mod utils;
@@ -0,0 +1,143 @@
+use actix_web::web::Data;
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::{event, Level};
+
+use crate::controller::user::create_login_response;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::oidc_login_state_dao::OidcLoginStateDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::oidc_login_state::{
+    OidcCallback, OidcLoginResponse, OidcLoginState,
+};
+use crate::database::entities::user::User;
+use crate::jwt_utils::{generate_opaque_token, JWTTtl, JwtKeyring, RefreshTokenTtl};
+use crate::oidc::{self, OidcIdTokenClaims};
+use crate::settings::Oidc;
+use crate::SETTINGS;
+
+/// Starts the login and returns the url of the identity provider, where the client has to continue.
+pub async fn login() -> actix_web::Result<HttpResponse> {
+    let oidc_settings = get_enabled_oidc_settings()?;
+    let metadata = oidc::discover(oidc_settings).await?;
+
+    let mut login_state = OidcLoginState::new(
+        generate_opaque_token(),
+        generate_opaque_token(),
+        generate_opaque_token(),
+    );
+    OidcLoginStateDAO::insert(&mut login_state).await?;
+
+    Ok(HttpResponse::Ok().json(OidcLoginResponse {
+        authorization_url: oidc::get_authorization_url(
+            &metadata,
+            oidc_settings,
+            login_state.state.as_str(),
+            login_state.nonce.as_str(),
+            login_state.code_verifier.as_str(),
+        ),
+    }))
+}
+
+/// Completes the login with the code and state the identity provider redirected with.
+/// A second factor of the user is not requested, the identity provider is responsible for it.
+pub async fn callback(
+    request: HttpRequest,
+    callback_data: web::Query<OidcCallback>,
+    jwt_keyring: Data<JwtKeyring>,
+    jwt_ttl: Data<JWTTtl>,
+    refresh_token_ttl: Data<RefreshTokenTtl>,
+) -> actix_web::Result<HttpResponse> {
+    let oidc_settings = get_enabled_oidc_settings()?;
+
+    let login_state = OidcLoginStateDAO::take_valid(callback_data.state.as_str())
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid or expired login state"))?;
+
+    let metadata = oidc::discover(oidc_settings).await?;
+    let claims = oidc::exchange_code(
+        &metadata,
+        oidc_settings,
+        callback_data.code.as_str(),
+        login_state.code_verifier.as_str(),
+        login_state.nonce.as_str(),
+    )
+    .await?;
+
+    let user = get_or_create_user(&claims, oidc_settings).await?;
+
+    Ok(HttpResponse::Ok().json(
+        create_login_response(&request, &user, &jwt_keyring, &jwt_ttl, &refresh_token_ttl).await?,
+    ))
+}
+
+/// Finds the user linked to the identity provider account.
+/// Existing users are linked on their first login if the provider verified their email,
+/// unless they use a second factor, which the login through the provider would skip.
+async fn get_or_create_user(
+    claims: &OidcIdTokenClaims,
+    oidc_settings: &Oidc,
+) -> actix_web::Result<User> {
+    if let Some(user) = UserDAO::get_by_oidc_subject(claims.sub.as_str()).await? {
+        return Ok(user);
+    }
+
+    let email = claims.email.as_ref().ok_or_else(|| {
+        actix_web::error::ErrorUnauthorized("The identity provider did not provide an email")
+    })?;
+    let email_verified = claims.email_verified.unwrap_or(false);
+
+    if let Some(mut user) = UserDAO::get_by_email(email.as_str()).await? {
+        if !email_verified || user.oidc_subject.is_some() {
+            return Err(actix_web::error::ErrorConflict(
+                "A different account with this email already exists",
+            ));
+        }
+        if user.totp_enabled {
+            return Err(actix_web::error::ErrorConflict(
+                "The account with this email uses two-factor authentication and cannot be linked",
+            ));
+        }
+
+        event!(
+            Level::INFO,
+            "linking user {:?} with identity provider account {}",
+            user.id,
+            claims.sub
+        );
+        user.oidc_subject = Some(claims.sub.clone());
+        user.email_verified = true;
+        UserDAO::update(&user).await?;
+        return Ok(user);
+    }
+
+    if !oidc_settings.create_users {
+        return Err(actix_web::error::ErrorForbidden(
+            "There is no account for this identity provider login",
+        ));
+    }
+
+    // the account can only be used with the identity provider, as nobody knows this password
+    let mut user = User::new(
+        claims.given_name.clone().unwrap_or_default(),
+        claims.family_name.clone().unwrap_or_default(),
+        email.to_owned(),
+        User::hash_password(generate_opaque_token().as_str())?,
+    );
+    user.email_verified = email_verified;
+    user.oidc_subject = Some(claims.sub.clone());
+
+    UserDAO::insert_with_root_dir(&mut user).await?;
+
+    Ok(user)
+}
+
+fn get_enabled_oidc_settings() -> actix_web::Result<&'static Oidc> {
+    let oidc_settings = &SETTINGS.get().unwrap().oidc;
+
+    if !oidc_settings.enabled {
+        return Err(actix_web::error::ErrorNotFound(
+            "OpenID Connect login is disabled",
+        ));
+    }
+    Ok(oidc_settings)
+}
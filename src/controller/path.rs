@@ -0,0 +1,113 @@
+use actix_jwt_authc::Authenticated;
+use actix_multipart::Multipart;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::archive::ArchiveMethod;
+use crate::controller::file::upload_into_directory;
+use crate::controller::utils::get_archive_file_stream_http_response;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::entities::directory::{PathDownloadQueryParams, PathGetResponse};
+use crate::database::entities::file::PathUploadQueryParams;
+use crate::jwt_utils::extract_user_oid;
+use crate::paths::{self, PathTarget};
+use crate::storage::storage_provider::StorageProvider;
+use crate::Claims;
+
+/// Returns the file or the directory with its content at the path below the root directory.
+pub async fn get(
+    _authenticated: Authenticated<Claims>,
+    path: Option<web::Path<String>>,
+) -> actix_web::Result<HttpResponse> {
+    let target = paths::resolve(
+        _authenticated.claims.thunder_root_dir_id,
+        extract_user_oid(&_authenticated),
+        get_path(&path),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(match target {
+        PathTarget::Directory(dir) => PathGetResponse::Directory {
+            id: dir.id.ok_or_else(|| {
+                actix_web::error::ErrorInternalServerError("directory id not found")
+            })?,
+            dirs: DirectoryDAO::get_all_with_parent_id_for_response(dir.id).await?,
+            files: dir.get_finished_files().await,
+            name: dir.name,
+            parent_id: dir.parent_id,
+            creation_date_ts: dir.creation_date.timestamp_millis(),
+        },
+        PathTarget::File(file) => PathGetResponse::File(file),
+    }))
+}
+
+/// Downloads the file at the path, a directory is downloaded as archive (tar by default).
+pub async fn download(
+    _authenticated: Authenticated<Claims>,
+    path: Option<web::Path<String>>,
+    query_params: web::Query<PathDownloadQueryParams>,
+    req: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    let target = paths::resolve(
+        _authenticated.claims.thunder_root_dir_id,
+        extract_user_oid(&_authenticated),
+        get_path(&path),
+    )
+    .await?;
+
+    match target {
+        PathTarget::File(file) => {
+            if query_params.archive.is_some() {
+                let archive_method = ArchiveMethod::extract_from_str_option(
+                    &query_params.archive,
+                    ArchiveMethod::Tar,
+                );
+                return get_archive_file_stream_http_response(
+                    archive_method,
+                    format!("{}.{}", &file.name, archive_method.extension()),
+                    StorageProvider::get_compressed_file_stream(&file, archive_method)?,
+                );
+            }
+
+            Ok(StorageProvider::get_named_file(&file)?.into_response(&req))
+        }
+        PathTarget::Directory(dir) => {
+            let archive_method =
+                ArchiveMethod::extract_from_str_option(&query_params.archive, ArchiveMethod::Tar);
+            let name = match dir.name.as_str() {
+                "/" => "root",
+                name => name,
+            };
+
+            get_archive_file_stream_http_response(
+                archive_method,
+                format!("{}.{}", name, archive_method.extension()),
+                StorageProvider::get_compressed_directory_stream(&dir, archive_method).await?,
+            )
+        }
+    }
+}
+
+/// Uploads the `file` fields of the multipart payload into the directory at the path.
+/// With `create_parents` the missing directories of the path are created first.
+pub async fn upload(
+    _authenticated: Authenticated<Claims>,
+    path: Option<web::Path<String>>,
+    query_params: web::Query<PathUploadQueryParams>,
+    payload: Multipart,
+) -> actix_web::Result<HttpResponse> {
+    let dir = paths::resolve_directory(
+        _authenticated.claims.thunder_root_dir_id,
+        extract_user_oid(&_authenticated),
+        get_path(&path),
+        query_params.create_parents,
+    )
+    .await?;
+
+    let uploaded_files = upload_into_directory(&dir, query_params.conflict, payload).await?;
+    Ok(HttpResponse::Ok().json(uploaded_files))
+}
+
+/// The bare routes have no path, they address the root directory.
+fn get_path(path: &Option<web::Path<String>>) -> &str {
+    path.as_ref().map_or("", |path| path.as_str())
+}
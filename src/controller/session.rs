@@ -0,0 +1,60 @@
+use std::str::FromStr;
+
+use actix_jwt_authc::Authenticated;
+use actix_web::web::Data;
+use actix_web::{web, HttpResponse};
+use mongodb::bson::oid::ObjectId;
+
+use crate::account;
+use crate::database::daos::dao::DAO;
+use crate::database::daos::session_dao::SessionDAO;
+use crate::database::entities::session::{Session, SessionInfo};
+use crate::jwt_utils::{extract_user_oid, InvalidatedJWTStore};
+use crate::Claims;
+
+/// Returns the devices the user is logged in with.
+pub async fn get_all(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
+    let current_session_id = _authenticated
+        .claims
+        .sid
+        .as_ref()
+        .and_then(|session_id| ObjectId::from_str(session_id.as_str()).ok());
+
+    let sessions: Vec<SessionInfo> =
+        SessionDAO::get_active_for_user(extract_user_oid(&_authenticated))
+            .await?
+            .iter()
+            .map(|session| session.get_info(current_session_id))
+            .collect();
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+/// Logs out a single device, its access tokens are rejected right away.
+pub async fn delete(
+    _authenticated: Authenticated<Claims>,
+    session_id: web::Path<ObjectId>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    let user_id = extract_user_oid(&_authenticated);
+    let session: Session = SessionDAO::get_with_user(*session_id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Requested session could not be found"))?;
+
+    SessionDAO::revoke(*session_id).await?;
+    invalidated_jwt_store
+        .invalidate_session(user_id, session.id.unwrap())
+        .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Logs out all devices, including the requesting one.
+pub async fn delete_all(
+    _authenticated: Authenticated<Claims>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    account::logout_everywhere(extract_user_oid(&_authenticated), &invalidated_jwt_store).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
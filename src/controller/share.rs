@@ -6,7 +6,7 @@ use mongodb::bson::oid::ObjectId;
 use mongodb::bson::DateTime;
 use std::str::FromStr;
 
-use crate::controller::utils::get_archive_file_stream_http_response;
+use crate::controller::utils::{ensure_verified_email, get_archive_file_stream_http_response};
 use crate::database::daos::dao::DAO;
 use crate::database::daos::directory_dao::DirectoryDAO;
 use crate::database::daos::file_dao::FileDAO;
@@ -51,7 +51,11 @@ pub async fn download(
 
         return match share.get_type() {
             ShareType::File => {
-                if let Some(file) = FileDAO::get(share.corresponding_id).await? {
+                // files in the trash cannot be downloaded
+                if let Some(file) = FileDAO::get(share.corresponding_id)
+                    .await?
+                    .filter(|file| file.deletion_date.is_none())
+                {
                     let mut archive_method: Option<ArchiveMethod> = None;
                     if (&share_get_data.archive).is_some() {
                         archive_method = Some(ArchiveMethod::extract_from_str_option(
@@ -77,7 +81,10 @@ pub async fn download(
                 ))
             }
             ShareType::Directory => {
-                if let Some(mut dir) = DirectoryDAO::get(share.corresponding_id).await? {
+                if let Some(mut dir) = DirectoryDAO::get(share.corresponding_id)
+                    .await?
+                    .filter(|dir| dir.deletion_date.is_none())
+                {
                     ShareDAO::register_share_download(&mut share).await?;
 
                     let archive_method = ArchiveMethod::extract_from_str_option(
@@ -172,6 +179,7 @@ pub async fn create_file_share(
     _authenticated: Authenticated<Claims>,
     create_share_data: Json<FileShareCreate>,
 ) -> actix_web::Result<HttpResponse> {
+    ensure_verified_email(&_authenticated).await?;
     let user_id = extract_user_oid(&_authenticated);
 
     if let Some(file) = FileDAO::get_file_by_uuid_for_user(&create_share_data.uuid, user_id).await?
@@ -198,6 +206,7 @@ pub async fn create_directory_share(
     _authenticated: Authenticated<Claims>,
     create_share_data: Json<DirectoryShareCreate>,
 ) -> actix_web::Result<HttpResponse> {
+    ensure_verified_email(&_authenticated).await?;
     let user_id = extract_user_oid(&_authenticated);
 
     if let Some(dir) = DirectoryDAO::get_with_user((&create_share_data.id).clone(), user_id).await?
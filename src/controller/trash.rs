@@ -0,0 +1,93 @@
+use actix_jwt_authc::Authenticated;
+use actix_web::web::Json;
+use actix_web::{web, HttpResponse};
+
+use crate::controller::utils::extract_object_id_or_die;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::entities::directory::{Directory, DirectoryDelete, TrashGetResponse};
+use crate::database::entities::file::{File, FileUuid};
+use crate::jwt_utils::extract_user_oid;
+use crate::trash;
+use crate::Claims;
+
+/// Lists the files and directories, that have been deleted on their own.
+/// The content of a deleted directory is not listed separately.
+pub async fn get(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
+    let user_id = extract_user_oid(&_authenticated);
+
+    Ok(HttpResponse::Ok().json(TrashGetResponse {
+        dirs: DirectoryDAO::get_trashed_for_user(user_id)
+            .await?
+            .iter()
+            .filter_map(Directory::get_trash_info)
+            .collect(),
+        files: FileDAO::get_trashed_for_user(user_id).await?,
+    }))
+}
+
+pub async fn restore_file(
+    _authenticated: Authenticated<Claims>,
+    file_data: Json<FileUuid>,
+) -> actix_web::Result<HttpResponse> {
+    let file = get_trashed_file(&_authenticated, &file_data.uuid).await?;
+
+    let file = trash::restore_file(file, _authenticated.claims.thunder_root_dir_id).await?;
+    Ok(HttpResponse::Ok().json(file))
+}
+
+pub async fn restore_directory(
+    _authenticated: Authenticated<Claims>,
+    dir_data: Json<DirectoryDelete>,
+) -> actix_web::Result<HttpResponse> {
+    let dir = get_trashed_directory(&_authenticated, &dir_data.id).await?;
+
+    let dir = trash::restore_directory(dir, _authenticated.claims.thunder_root_dir_id).await?;
+    Ok(HttpResponse::Ok().json(dir.id))
+}
+
+pub async fn delete_file(
+    _authenticated: Authenticated<Claims>,
+    query_params: web::Query<FileUuid>,
+) -> actix_web::Result<HttpResponse> {
+    let file = get_trashed_file(&_authenticated, &query_params.uuid).await?;
+
+    trash::delete_file(&file).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn delete_directory(
+    _authenticated: Authenticated<Claims>,
+    query_params: web::Query<DirectoryDelete>,
+) -> actix_web::Result<HttpResponse> {
+    let dir = get_trashed_directory(&_authenticated, &query_params.id).await?;
+
+    trash::delete_directory(&dir).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn empty(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
+    trash::empty(extract_user_oid(&_authenticated)).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn get_trashed_file(
+    authenticated: &Authenticated<Claims>,
+    uuid: &str,
+) -> actix_web::Result<File> {
+    FileDAO::get_trashed_by_uuid_for_user(uuid, extract_user_oid(authenticated))
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("File not found in the trash"))
+}
+
+async fn get_trashed_directory(
+    authenticated: &Authenticated<Claims>,
+    id: &String,
+) -> actix_web::Result<Directory> {
+    DirectoryDAO::get_trashed_with_user(
+        extract_object_id_or_die(Some(id))?,
+        extract_user_oid(authenticated),
+    )
+    .await?
+    .ok_or_else(|| actix_web::error::ErrorBadRequest("Directory not found in the trash"))
+}
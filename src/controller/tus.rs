@@ -0,0 +1,319 @@
+use std::io::Write;
+use std::str::FromStr;
+
+use actix_jwt_authc::Authenticated;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures_util::TryStreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, Uuid};
+use ring::digest;
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::file::File;
+use crate::jwt_utils::extract_user_oid;
+use crate::quota;
+use crate::storage::storage_provider::StorageProvider;
+use crate::tus::{self, UploadLock};
+use crate::{Claims, SETTINGS};
+
+/// Announces the supported protocol version and extensions.
+pub async fn options() -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header((tus::TUS_RESUMABLE_HEADER, tus::TUS_VERSION))
+        .insert_header((tus::TUS_VERSION_HEADER, tus::TUS_VERSION))
+        .insert_header((tus::TUS_EXTENSION_HEADER, tus::TUS_EXTENSIONS))
+        .insert_header((
+            tus::TUS_CHECKSUM_ALGORITHM_HEADER,
+            tus::TUS_CHECKSUM_ALGORITHMS,
+        ))
+        .finish()
+}
+
+/// Creates an unfinished file, the content is appended by PATCH requests to the returned location.
+/// `Upload-Metadata` can contain `filename`, `filetype`, `directory` (id, default is the root
+/// directory) and `mtime` (timestamp with milliseconds).
+pub async fn create(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+) -> actix_web::Result<HttpResponse> {
+    tus::ensure_tus_resumable(&request)?;
+    let user_id = extract_user_oid(&_authenticated);
+
+    let upload_length = tus::get_length_header(&request, tus::UPLOAD_LENGTH_HEADER)?;
+    let metadata = tus::parse_metadata(
+        tus::get_header(&request, tus::UPLOAD_METADATA_HEADER).unwrap_or_default(),
+    )
+    .ok_or_else(|| tus::get_error(StatusCode::BAD_REQUEST, "Invalid Upload-Metadata"))?;
+
+    let directory_id = match metadata.get("directory") {
+        Some(directory_id) => ObjectId::from_str(directory_id).map_err(|_| {
+            tus::get_error(StatusCode::BAD_REQUEST, "Directory id is not parseable")
+        })?,
+        None => _authenticated.claims.thunder_root_dir_id,
+    };
+    let dir = DirectoryDAO::get_with_user(directory_id, user_id)
+        .await?
+        .ok_or_else(|| tus::get_error(StatusCode::NOT_FOUND, "Directory not found"))?;
+
+    let filename = metadata
+        .get("filename")
+        .map_or_else(|| Uuid::new().to_string(), sanitize_filename::sanitize);
+    if dir.has_file_with_name(&filename).await {
+        return Err(tus::get_error(
+            StatusCode::CONFLICT,
+            "There is already a file with the given name in the directory",
+        ));
+    }
+
+    let modification_date = match metadata.get("mtime") {
+        Some(mtime) => Some(DateTime::from_millis(mtime.parse::<i64>().map_err(
+            |_| {
+                tus::get_error(
+                    StatusCode::BAD_REQUEST,
+                    "Metadata mtime has to be a timestamp with milliseconds",
+                )
+            },
+        )?)),
+        None => None,
+    };
+
+    // fail early, the quota is enforced when the parts are received
+    let user = UserDAO::get(user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?;
+    let quota_bytes = user.get_quota_bytes();
+    if matches!(quota_bytes, Some(quota_bytes) if user.used_bytes + upload_length > quota_bytes) {
+        return Err(quota::get_quota_exceeded_error(quota_bytes));
+    }
+
+    let mut file = File {
+        id: None,
+        parent_id: directory_id,
+        user_id,
+        uuid: Uuid::new().to_string(),
+        hash: "".to_string(),
+        size: 0,
+        mime: metadata
+            .get("filetype")
+            .cloned()
+            .unwrap_or_else(|| mime::APPLICATION_OCTET_STREAM.to_string()),
+        name: filename,
+        finished: false,
+        upload_length: Some(upload_length),
+        upload_expiration_date: Some(tus::get_expiration_date()),
+        upload_lock: None,
+        upload_locked_until: None,
+        creation_date: DateTime::now(),
+        modification_date,
+        deletion_date: None,
+        trashed_with: None,
+    };
+
+    StorageProvider::create_file_handle(file.uuid.clone()).await?;
+    FileDAO::insert(&mut file).await?;
+    if upload_length == 0 {
+        finish_upload(&mut file).await?;
+    }
+
+    let mut response = HttpResponse::Created();
+    response
+        .insert_header((tus::TUS_RESUMABLE_HEADER, tus::TUS_VERSION))
+        .insert_header((
+            header::LOCATION,
+            format!(
+                "{}/v1/data/tus/{}",
+                SETTINGS.get().unwrap().server.url.trim_end_matches('/'),
+                file.uuid
+            ),
+        ));
+    if let Some(upload_expiration_date) = file.upload_expiration_date {
+        response.insert_header((
+            tus::UPLOAD_EXPIRES_HEADER,
+            tus::format_expiration_date(upload_expiration_date),
+        ));
+    }
+    Ok(response.finish())
+}
+
+/// Returns the offset, from which the client has to continue the upload.
+pub async fn head(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+    uuid: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    tus::ensure_tus_resumable(&request)?;
+    let file = get_upload(&_authenticated, uuid.as_str()).await?;
+
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((tus::TUS_RESUMABLE_HEADER, tus::TUS_VERSION))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .insert_header((tus::UPLOAD_OFFSET_HEADER, file.size.to_string()))
+        .insert_header((
+            tus::UPLOAD_LENGTH_HEADER,
+            file.upload_length.unwrap_or_default().to_string(),
+        ));
+    if let Some(upload_expiration_date) = file.upload_expiration_date {
+        response.insert_header((
+            tus::UPLOAD_EXPIRES_HEADER,
+            tus::format_expiration_date(upload_expiration_date),
+        ));
+    }
+    Ok(response.finish())
+}
+
+/// Appends the body at `Upload-Offset`, which has to match the received bytes so far.
+/// With `Upload-Checksum` the part is discarded, if it does not match the checksum.
+pub async fn patch(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+    uuid: web::Path<String>,
+    mut payload: web::Payload,
+) -> actix_web::Result<HttpResponse> {
+    tus::ensure_tus_resumable(&request)?;
+    if tus::get_header(&request, header::CONTENT_TYPE.as_str())
+        != Some(tus::TUS_OFFSET_CONTENT_TYPE)
+    {
+        return Err(tus::get_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Content-Type has to be application/offset+octet-stream",
+        ));
+    }
+    let offset = tus::get_length_header(&request, tus::UPLOAD_OFFSET_HEADER)?;
+    let checksum = match tus::get_header(&request, tus::UPLOAD_CHECKSUM_HEADER) {
+        Some(checksum) => Some(tus::parse_checksum(checksum).ok_or_else(|| {
+            tus::get_error(StatusCode::BAD_REQUEST, "Unsupported checksum algorithm")
+        })?),
+        None => None,
+    };
+
+    let mut file = get_upload(&_authenticated, uuid.as_str()).await?;
+    let upload_length = file.upload_length.unwrap_or_default();
+
+    if file.finished || file.size != offset {
+        return Err(tus::get_error(
+            StatusCode::CONFLICT,
+            "Upload-Offset does not match the offset of the upload",
+        ));
+    }
+    if matches!(file.upload_expiration_date, Some(date) if date < DateTime::now()) {
+        return Err(tus::get_error(StatusCode::GONE, "The upload has expired"));
+    }
+
+    // fails, if another request appends to the upload or has changed its offset in the meantime
+    let mut lock = UploadLock::acquire(&file).await?;
+    // drops the bytes of an interrupted request, that have not been booked as part of the upload
+    StorageProvider::truncate_file(file.uuid.clone(), offset as u64).await?;
+
+    let mut storage_file = StorageProvider::open_file_for_append(file.uuid.clone()).await?;
+    let mut context = digest::Context::new(
+        checksum
+            .as_ref()
+            .map_or(&digest::SHA256, |(algorithm, _)| *algorithm),
+    );
+    let mut written_bytes: i64 = 0;
+
+    while let Some(chunk) = payload.try_next().await? {
+        lock.renew_if_due().await?;
+        written_bytes += chunk.len() as i64;
+        if offset + written_bytes > upload_length {
+            StorageProvider::truncate_file(file.uuid.clone(), offset as u64).await?;
+            return Err(tus::get_error(
+                StatusCode::BAD_REQUEST,
+                "The part exceeds the Upload-Length",
+            ));
+        }
+
+        // filesystem operations are blocking, may we have to use threadpool
+        (storage_file, context) = web::block(move || {
+            context.update(&chunk);
+            storage_file
+                .write_all(&chunk)
+                .map(|_| (storage_file, context))
+        })
+        .await??;
+    }
+
+    if let Some((_, expected_checksum)) = &checksum {
+        if context.finish().as_ref() != expected_checksum.as_slice() {
+            StorageProvider::truncate_file(file.uuid.clone(), offset as u64).await?;
+            return Err(tus::get_checksum_mismatch_error());
+        }
+    }
+
+    let user = UserDAO::get(file.user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?;
+    if let Err(e) = quota::reserve(file.user_id, written_bytes as u64, user.get_quota_bytes()).await
+    {
+        StorageProvider::truncate_file(file.uuid.clone(), offset as u64).await?;
+        return Err(e);
+    }
+
+    file.size = offset + written_bytes;
+    if file.size == upload_length {
+        finish_upload(&mut file).await?;
+    } else {
+        file.upload_expiration_date = Some(tus::get_expiration_date());
+        FileDAO::update(&file).await?;
+    }
+    lock.release().await?;
+
+    let mut response = HttpResponse::NoContent();
+    response
+        .insert_header((tus::TUS_RESUMABLE_HEADER, tus::TUS_VERSION))
+        .insert_header((tus::UPLOAD_OFFSET_HEADER, file.size.to_string()));
+    if let Some(upload_expiration_date) = file.upload_expiration_date {
+        response.insert_header((
+            tus::UPLOAD_EXPIRES_HEADER,
+            tus::format_expiration_date(upload_expiration_date),
+        ));
+    }
+    Ok(response.finish())
+}
+
+/// Terminates the unfinished upload and removes the received data.
+/// Finished files are not affected, they are deleted through the trash like all other files.
+pub async fn delete(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+    uuid: web::Path<String>,
+) -> actix_web::Result<HttpResponse> {
+    tus::ensure_tus_resumable(&request)?;
+
+    let file = FileDAO::get_unfinished_upload_by_uuid_for_user(
+        uuid.as_str(),
+        extract_user_oid(&_authenticated),
+    )
+    .await?
+    .ok_or_else(|| tus::get_error(StatusCode::NOT_FOUND, "Upload not found"))?;
+
+    let lock = UploadLock::acquire(&file).await?;
+    quota::delete_file_blob(&file).await?;
+    FileDAO::delete(&file).await?;
+    lock.release().await?;
+
+    Ok(HttpResponse::NoContent()
+        .insert_header((tus::TUS_RESUMABLE_HEADER, tus::TUS_VERSION))
+        .finish())
+}
+
+async fn get_upload(authenticated: &Authenticated<Claims>, uuid: &str) -> actix_web::Result<File> {
+    FileDAO::get_upload_by_uuid_for_user(uuid, extract_user_oid(authenticated))
+        .await?
+        .ok_or_else(|| tus::get_error(StatusCode::NOT_FOUND, "Upload not found"))
+}
+
+/// Makes the completely received file visible to listings and sync clients.
+async fn finish_upload(file: &mut File) -> actix_web::Result<()> {
+    file.hash = StorageProvider::get_file_sha256(file.uuid.clone()).await?;
+    file.finished = true;
+    file.upload_expiration_date = None;
+    FileDAO::update(file).await?;
+    FileDAO::insert_create_sync_state(file).await
+}
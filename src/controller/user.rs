@@ -1,95 +1,571 @@
-use std::ops::Add;
+use std::str::FromStr;
 
 use actix_jwt_authc::Authenticated;
+use actix_web::http::header;
 use actix_web::web::Data;
-use actix_web::{web::Json, HttpResponse};
-use jsonwebtoken::{encode, EncodingKey, Header};
-use serde::Serialize;
-use time::OffsetDateTime;
+use actix_web::{web, web::Json, HttpRequest, HttpResponse};
+use mongodb::bson::oid::ObjectId;
 use tracing::{event, Level};
 
+use crate::account;
+use crate::authenticator;
 use crate::database::daos::dao::DAO;
-use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::daos::invitation_dao::InvitationDAO;
+use crate::database::daos::session_dao::SessionDAO;
 use crate::database::daos::user_dao::UserDAO;
+use crate::database::daos::verification_token_dao::VerificationTokenDAO;
+use crate::database::entities::session::{RefreshTokenCheck, Session, SessionRefresh};
 use crate::database::entities::user::{
-    LoginResponse, LogoutResponse, Role, User, UserLogin, UserRegister,
+    AccountDelete, AccountDeleteResponse, AccountRestore, LoginResponse, LogoutResponse,
+    MfaPendingResponse, PasswordChange, TotpCode, TotpConfirmResponse, TotpEnrollResponse, User,
+    UserLogin, UserLoginMfa, UserRegister, UserUpdate,
 };
-use crate::jwt_utils::{JWTTtl, JWT_SIGNING_ALGO};
+use crate::database::entities::verification_token::{
+    EmailVerify, PasswordResetConfirm, PasswordResetRequest, VerificationPurpose, VerificationToken,
+};
+use crate::jwt_utils::{
+    create_jwt, create_mfa_pending_jwt, extract_user_oid, generate_opaque_token, hash_opaque_token,
+    JWTTtl, JwtKeyring, MfaPendingClaims, RefreshTokenTtl,
+};
+use crate::mail::mailer::Mailer;
+use crate::mail::templates::MailTemplate;
+use crate::throttling;
+use crate::totp;
 use crate::{Claims, InvalidatedJWTStore, SETTINGS};
 
-#[derive(Serialize)]
-pub struct TestResponse {
-    pub session_info: Authenticated<Claims>,
-    pub email: String,
-}
+const TOTP_RECOVERY_CODE_COUNT: usize = 10;
 
 pub async fn login(
+    request: HttpRequest,
     login_user: Json<UserLogin>,
-    jwt_encoding_key: Data<EncodingKey>,
+    jwt_keyring: Data<JwtKeyring>,
     jwt_ttl: Data<JWTTtl>,
+    refresh_token_ttl: Data<RefreshTokenTtl>,
 ) -> actix_web::Result<HttpResponse> {
     event!(Level::INFO, "login_user: {}", login_user.email);
 
-    let user = UserDAO::get_by_email(login_user.email.as_str()).await?;
-
-    if let Some(user) = user {
-        if let (Some(id), Some(root_dir_id)) = (user.id, user.root_dir_id) {
-            let sub = id.to_string();
-            let thunder_root_dir_id = root_dir_id;
-            let iat = OffsetDateTime::now_utc().unix_timestamp() as usize;
-            let expires_at = OffsetDateTime::now_utc().add(jwt_ttl.0);
-            let exp = expires_at.unix_timestamp() as usize;
-
-            let jwt_claims = Claims {
-                iat,
-                exp,
-                sub,
-                thunder_root_dir_id,
-            };
-            let jwt_token = encode(
-                &Header::new(JWT_SIGNING_ALGO),
-                &jwt_claims,
-                &jwt_encoding_key,
-            )
-            .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    let user = authenticate_throttled(&request, &login_user).await?;
+    ensure_enabled(&user)?;
+
+    if user.totp_enabled {
+        // the failed attempts are only forgotten after the second factor has been checked
+        return Ok(HttpResponse::Ok().json(MfaPendingResponse {
+            mfa_token: create_mfa_pending_jwt(user.id.unwrap(), &jwt_keyring)?,
+        }));
+    }
+    throttling::register_success(user.email.as_str()).await?;
+
+    Ok(HttpResponse::Ok().json(
+        create_login_response(&request, &user, &jwt_keyring, &jwt_ttl, &refresh_token_ttl).await?,
+    ))
+}
+
+pub async fn login_mfa(
+    request: HttpRequest,
+    login_data: Json<UserLoginMfa>,
+    jwt_keyring: Data<JwtKeyring>,
+    jwt_ttl: Data<JWTTtl>,
+    refresh_token_ttl: Data<RefreshTokenTtl>,
+) -> actix_web::Result<HttpResponse> {
+    let claims = jwt_keyring
+        .decode::<MfaPendingClaims>(login_data.mfa_token.as_str())
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or expired mfa token"))?;
+    let user_id =
+        ObjectId::from_str(claims.sub.as_str()).map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut user = UserDAO::get(user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?;
+
+    let client_ip = throttling::get_client_ip(&request);
+    throttling::ensure_not_locked(Some(user.email.as_str()), client_ip.as_str()).await?;
+    if !use_second_factor(&mut user, login_data.code.as_str()).await? {
+        throttling::register_failure(Some(user.email.as_str()), client_ip.as_str()).await?;
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Invalid second factor code",
+        ));
+    }
+    throttling::register_success(user.email.as_str()).await?;
+
+    Ok(HttpResponse::Ok().json(
+        create_login_response(&request, &user, &jwt_keyring, &jwt_ttl, &refresh_token_ttl).await?,
+    ))
+}
+
+pub async fn enroll_totp(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
+    let mut user = get_authenticated_user(&_authenticated).await?;
+
+    if user.totp_enabled {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Two-factor authentication is already enabled",
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    user.totp_secret = Some(secret.clone());
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().json(TotpEnrollResponse {
+        provisioning_uri: totp::get_provisioning_uri(
+            secret.as_str(),
+            user.email.as_str(),
+            SETTINGS.get().unwrap().app_name.as_str(),
+        ),
+        secret,
+    }))
+}
+
+pub async fn confirm_totp(
+    _authenticated: Authenticated<Claims>,
+    totp_data: Json<TotpCode>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_authenticated_user(&_authenticated).await?;
+
+    if user.totp_enabled || user.totp_secret.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "There is no pending two-factor authentication enrollment",
+        ));
+    }
+    if !user.verify_totp_code(totp_data.code.as_str()) {
+        return Err(actix_web::error::ErrorBadRequest("Invalid code"));
+    }
+
+    let recovery_codes = totp::generate_recovery_codes(TOTP_RECOVERY_CODE_COUNT);
+    user.totp_recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| hash_opaque_token(code))
+        .collect();
+    user.totp_enabled = true;
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().json(TotpConfirmResponse { recovery_codes }))
+}
+
+pub async fn disable_totp(
+    _authenticated: Authenticated<Claims>,
+    totp_data: Json<TotpCode>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_authenticated_user(&_authenticated).await?;
+
+    if !use_second_factor(&mut user, totp_data.code.as_str()).await? {
+        return Err(actix_web::error::ErrorBadRequest("Invalid code"));
+    }
+
+    user.reset_totp();
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Checks a totp or recovery code of the enabled two-factor authentication and marks it as used.
+/// The database only accepts each code once, so parallel requests cannot use the same code.
+async fn use_second_factor(user: &mut User, code: &str) -> actix_web::Result<bool> {
+    if !user.totp_enabled {
+        return Ok(false);
+    }
+    let user_id = user
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("User is missing id"))?;
+
+    if user.verify_totp_code(code) {
+        return UserDAO::use_totp_step(user_id, user.totp_last_used_step.unwrap_or_default()).await;
+    }
+    if user.use_totp_recovery_code(code) {
+        return UserDAO::use_totp_recovery_code(
+            user_id,
+            User::hash_totp_recovery_code(code).as_str(),
+        )
+        .await;
+    }
+    Ok(false)
+}
+
+fn ensure_enabled(user: &User) -> actix_web::Result<()> {
+    if user.disabled {
+        return Err(actix_web::error::ErrorForbidden(
+            "The account has been disabled by an administrator",
+        ));
+    }
+    if user.deletion_date.is_some() {
+        if user.is_federated() {
+            return Err(actix_web::error::ErrorForbidden(
+                "The account is scheduled for deletion, it can be restored by an administrator",
+            ));
+        }
+        return Err(actix_web::error::ErrorForbidden(
+            "The account is scheduled for deletion, it can be restored via /v1/user/account/restore",
+        ));
+    }
+    Ok(())
+}
+
+pub async fn delete_account(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+    delete_data: Json<AccountDelete>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_authenticated_user(&_authenticated).await?;
+    ensure_not_federated(&user)?;
+
+    // require the current credentials, so a stolen access token cannot delete the account
+    confirm_credentials(&request, &user, delete_data.pw_hash.as_str()).await?;
+
+    account::request_deletion(&mut user, false, &invalidated_jwt_store).await?;
+
+    Ok(HttpResponse::Ok().json(AccountDeleteResponse {
+        deletion_date_ts: user.deletion_date.map(|date| date.timestamp_millis()),
+    }))
+}
 
-            return Ok(HttpResponse::Ok().json(LoginResponse { jwt: jwt_token }));
+/// Restores an account during the deletion grace period, the user has to log in again afterwards.
+/// With two-factor authentication enabled, a totp or recovery code is required as well.
+pub async fn restore_account(
+    request: HttpRequest,
+    restore_data: Json<AccountRestore>,
+) -> actix_web::Result<HttpResponse> {
+    let restore_data = restore_data.into_inner();
+    let mut user = authenticate_throttled(
+        &request,
+        &UserLogin {
+            email: restore_data.email,
+            pw_hash: restore_data.pw_hash,
+            password: None,
+        },
+    )
+    .await?;
+    ensure_not_federated(&user)?;
+
+    if user.totp_enabled {
+        let code = restore_data.code.unwrap_or_default();
+        if !use_second_factor(&mut user, code.as_str()).await? {
+            let client_ip = throttling::get_client_ip(&request);
+            throttling::register_failure(Some(user.email.as_str()), client_ip.as_str()).await?;
+            return Err(actix_web::error::ErrorUnauthorized(
+                "Invalid second factor code",
+            ));
         }
     }
 
-    return Err(actix_web::error::ErrorNotFound(
-        "User with email does not exist",
-    ));
+    account::restore(&mut user).await?;
+
+    Ok(HttpResponse::Ok().finish())
 }
 
-//todo: remove
-pub async fn test(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
-    if let Some(user) = UserDAO::get_authenticated(&_authenticated).await? {
-        return Ok(HttpResponse::Ok().json(TestResponse {
-            session_info: _authenticated.clone(),
-            email: user.email,
-        }));
+/// Checks the current credentials of the already authenticated user, throttled like a login.
+async fn confirm_credentials(
+    request: &HttpRequest,
+    user: &User,
+    pw_hash: &str,
+) -> actix_web::Result<()> {
+    let client_ip = throttling::get_client_ip(request);
+    throttling::ensure_not_locked(Some(user.email.as_str()), client_ip.as_str()).await?;
+
+    let confirmed_user = authenticator::authenticate(&UserLogin {
+        email: user.email.clone(),
+        pw_hash: pw_hash.to_string(),
+        password: None,
+    })
+    .await?;
+    if confirmed_user.and_then(|confirmed_user| confirmed_user.id) != user.id {
+        throttling::register_failure(Some(user.email.as_str()), client_ip.as_str()).await?;
+        return Err(actix_web::error::ErrorUnauthorized("Invalid password"));
+    }
+    Ok(())
+}
+
+/// The email, names and password of directory accounts are synced on every login, so they cannot be changed here.
+fn ensure_not_directory_managed(user: &User) -> actix_web::Result<()> {
+    if user.ldap_dn.is_some() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The account is managed by the directory service",
+        ));
+    }
+    Ok(())
+}
+
+/// Accounts of an identity provider or a directory have no local credentials to confirm the
+/// deletion with, they are deleted and restored by an administrator.
+fn ensure_not_federated(user: &User) -> actix_web::Result<()> {
+    if user.is_federated() {
+        return Err(actix_web::error::ErrorForbidden(
+            "Accounts linked to an identity provider or a directory can only be deleted and restored by an administrator",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the credentials, unless the account or the client ip is locked after too many failed attempts.
+async fn authenticate_throttled(
+    request: &HttpRequest,
+    login_user: &UserLogin,
+) -> actix_web::Result<User> {
+    let client_ip = throttling::get_client_ip(request);
+    throttling::ensure_not_locked(Some(login_user.email.as_str()), client_ip.as_str()).await?;
+
+    match authenticator::authenticate(login_user).await? {
+        Some(user) => Ok(user),
+        None => {
+            throttling::register_failure(Some(login_user.email.as_str()), client_ip.as_str())
+                .await?;
+            Err(actix_web::error::ErrorUnauthorized(
+                "Invalid email or password",
+            ))
+        }
+    }
+}
+
+async fn get_authenticated_user(authenticated: &Authenticated<Claims>) -> actix_web::Result<User> {
+    UserDAO::get_authenticated(authenticated)
+        .await?
+        .ok_or_else(|| {
+            actix_web::error::ErrorExpectationFailed("authenticated user not found in database")
+        })
+}
+
+pub async fn refresh(
+    refresh_data: Json<SessionRefresh>,
+    jwt_keyring: Data<JwtKeyring>,
+    jwt_ttl: Data<JWTTtl>,
+    refresh_token_ttl: Data<RefreshTokenTtl>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    let refresh_token_hash = hash_opaque_token(refresh_data.refresh_token.as_str());
+
+    let mut session = SessionDAO::get_by_refresh_token_hash(refresh_token_hash.as_str())
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("Invalid refresh token"))?;
+
+    let new_refresh_token = generate_opaque_token();
+    let rotated = match session.check_refresh_token(refresh_token_hash.as_str()) {
+        RefreshTokenCheck::Inactive => {
+            return Err(actix_web::error::ErrorUnauthorized(
+                "Session expired or revoked",
+            ));
+        }
+        // fails, if a parallel request has rotated the token in the meantime
+        RefreshTokenCheck::Current => {
+            SessionDAO::rotate_refresh_token(
+                &mut session,
+                hash_opaque_token(new_refresh_token.as_str()),
+                Session::get_expiration_date(refresh_token_ttl.0),
+            )
+            .await?
+        }
+        RefreshTokenCheck::Reused => false,
+    };
+
+    if !rotated {
+        // an already used refresh token has been presented again, it may have been stolen
+        event!(
+            Level::WARN,
+            "refresh token reuse detected, revoking session {:?}",
+            session.id
+        );
+        SessionDAO::revoke(session.id.unwrap()).await?;
+        // also the access tokens already issued with the stolen refresh token
+        invalidated_jwt_store
+            .invalidate_session(session.user_id, session.id.unwrap())
+            .await?;
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Refresh token has already been used",
+        ));
+    }
+
+    let user = UserDAO::get(session.user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?;
+    ensure_enabled(&user)?;
+
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        jwt: create_jwt(
+            session.user_id,
+            user.root_dir_id.ok_or_else(|| {
+                actix_web::error::ErrorInternalServerError("User has no root directory")
+            })?,
+            session.id.unwrap(),
+            &jwt_keyring,
+            &jwt_ttl,
+        )?,
+        refresh_token: new_refresh_token,
+    }))
+}
+
+/// Starts a new session for the user and returns the initial access and refresh token.
+pub async fn create_login_response(
+    request: &HttpRequest,
+    user: &User,
+    jwt_keyring: &JwtKeyring,
+    jwt_ttl: &JWTTtl,
+    refresh_token_ttl: &RefreshTokenTtl,
+) -> actix_web::Result<LoginResponse> {
+    ensure_enabled(user)?;
+
+    if let (Some(id), Some(root_dir_id)) = (user.id, user.root_dir_id) {
+        let refresh_token = generate_opaque_token();
+        let session_id = SessionDAO::insert(&mut Session::new(
+            id,
+            hash_opaque_token(refresh_token.as_str()),
+            refresh_token_ttl.0,
+            request
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|user_agent| user_agent.to_str().ok())
+                .map(str::to_string),
+            Some(throttling::get_client_ip(request)),
+        ))
+        .await?;
+
+        let jwt = create_jwt(id, root_dir_id, session_id, jwt_keyring, jwt_ttl)?;
+
+        return Ok(LoginResponse { jwt, refresh_token });
     }
-    Err(actix_web::error::ErrorExpectationFailed(
-        "authenticated user not found in database",
+
+    Err(actix_web::error::ErrorInternalServerError(
+        "User is missing id or root directory",
     ))
 }
 
+pub async fn get_me(_authenticated: Authenticated<Claims>) -> actix_web::Result<HttpResponse> {
+    let user = get_authenticated_user(&_authenticated).await?;
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+pub async fn update_me(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+    update_data: Json<UserUpdate>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_authenticated_user(&_authenticated).await?;
+    ensure_not_directory_managed(&user)?;
+
+    if let Some(firstname) = &update_data.firstname {
+        user.firstname = firstname.to_owned();
+    }
+    if let Some(lastname) = &update_data.lastname {
+        user.lastname = lastname.to_owned();
+    }
+
+    let email_changed = match &update_data.email {
+        Some(email) if *email != user.email => {
+            // require the current password, so a stolen access token cannot take over the account
+            confirm_credentials(
+                &request,
+                &user,
+                update_data.pw_hash.as_deref().unwrap_or_default(),
+            )
+            .await?;
+
+            if UserDAO::exists(email).await? {
+                return Err(actix_web::error::ErrorExpectationFailed(
+                    "User with email already exists",
+                ));
+            }
+            user.email = email.to_owned();
+            user.email_verified = false;
+            true
+        }
+        _ => false,
+    };
+
+    UserDAO::update(&user).await?;
+
+    if email_changed {
+        let user_id = user.id.unwrap();
+        // reset links sent to the old address must not work anymore
+        VerificationTokenDAO::delete_for_user_and_purpose(
+            user_id,
+            VerificationPurpose::PasswordReset,
+        )
+        .await?;
+        if let Err(e) = send_verification_mail(&user).await {
+            event!(
+                Level::ERROR,
+                "could not send verification mail to user {}: {}",
+                user_id,
+                e
+            );
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(user.get_info()))
+}
+
+/// Changes the password and logs out all other devices, the requesting session stays logged in.
+pub async fn change_password(
+    _authenticated: Authenticated<Claims>,
+    request: HttpRequest,
+    password_data: Json<PasswordChange>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    let mut user = get_authenticated_user(&_authenticated).await?;
+    ensure_not_directory_managed(&user)?;
+
+    if !User::is_valid_hash_design(password_data.new_pw_hash.as_str()) {
+        // not a hex encoded hash or less than 256 bit size
+        return Err(actix_web::error::ErrorExpectationFailed(
+            "Please provide at least a hex encoded sha256 hash",
+        ));
+    }
+    confirm_credentials(&request, &user, password_data.pw_hash.as_str()).await?;
+
+    user.pw_hash = User::hash_password(password_data.new_pw_hash.as_str())?;
+    UserDAO::update(&user).await?;
+
+    let current_session_id = _authenticated
+        .claims
+        .sid
+        .as_ref()
+        .and_then(|session_id| ObjectId::from_str(session_id.as_str()).ok());
+    account::logout_other_sessions(user.id.unwrap(), current_session_id, &invalidated_jwt_store)
+        .await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
 pub async fn logout(
     _authenticated: Authenticated<Claims>,
     invalidated_jwts: Data<InvalidatedJWTStore>,
-) -> HttpResponse {
-    HttpResponse::Ok().json(LogoutResponse {
+) -> actix_web::Result<HttpResponse> {
+    // also end the session, so neither its refresh token nor its other access tokens work anymore
+    if let Some(session_id) = _authenticated.claims.sid.as_ref() {
+        let session_id = ObjectId::from_str(session_id.as_str())
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        SessionDAO::revoke(session_id).await?;
+        invalidated_jwts
+            .invalidate_session(extract_user_oid(&_authenticated), session_id)
+            .await?;
+    }
+
+    Ok(HttpResponse::Ok().json(LogoutResponse {
         status: invalidated_jwts.add_to_invalidated(_authenticated).await,
-    })
+    }))
 }
 
-pub async fn register(new_user: Json<UserRegister>) -> actix_web::Result<HttpResponse> {
+pub async fn register(
+    request: HttpRequest,
+    new_user: Json<UserRegister>,
+) -> actix_web::Result<HttpResponse> {
+    // only the ip is throttled, the email of a registration does not belong to an account yet
+    let client_ip = throttling::get_client_ip(&request);
+    throttling::ensure_not_locked(None, client_ip.as_str()).await?;
+
+    let register_result = register_user(&new_user).await;
+    if let Err(e) = &register_result {
+        if e.as_response_error().status_code().is_client_error() {
+            throttling::register_failure(None, client_ip.as_str()).await?;
+        }
+    }
+    register_result
+}
+
+async fn register_user(new_user: &UserRegister) -> actix_web::Result<HttpResponse> {
     let settings = SETTINGS.get().unwrap();
 
-    if !settings.enable_public_registration {
+    if !settings.enable_public_registration && new_user.invitation_code.is_none() {
         return Err(actix_web::error::ErrorForbidden(
-            "Public registration is disabled at the moment",
+            "Public registration is disabled at the moment, an invitation code is required",
         ));
     }
 
@@ -106,24 +582,218 @@ pub async fn register(new_user: Json<UserRegister>) -> actix_web::Result<HttpRes
         ));
     }
 
-    let mut data = User {
-        id: None,
-        firstname: new_user.firstname.to_owned(),
-        lastname: new_user.lastname.to_owned(),
-        email: new_user.email.to_owned(),
-        pw_hash: new_user.pw_hash.to_owned(),
-        role: Role::BaseUser,
-        root_dir_id: None,
+    let invitation = match &new_user.invitation_code {
+        Some(invitation_code) => Some(
+            InvitationDAO::claim(
+                hash_opaque_token(invitation_code.as_str()).as_str(),
+                new_user.email.as_str(),
+            )
+            .await?
+            .ok_or_else(|| {
+                actix_web::error::ErrorForbidden("Invalid, expired or exhausted invitation code")
+            })?,
+        ),
+        None => None,
     };
 
-    let inserted_user_id = UserDAO::insert(&mut data)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    let mut data = User::new(
+        new_user.firstname.to_owned(),
+        new_user.lastname.to_owned(),
+        new_user.email.to_owned(),
+        User::hash_password(new_user.pw_hash.as_str())?,
+    );
+    data.email_verified = !settings.mail.require_email_verification;
 
-    let root_dir_id = DirectoryDAO::create_user_root_dir(inserted_user_id).await?;
+    let inserted_user_id = match UserDAO::insert_with_root_dir(&mut data).await {
+        Ok(inserted_user_id) => inserted_user_id,
+        Err(e) => {
+            // the registration can be retried with the same code
+            if let Some(invitation_id) = invitation.as_ref().and_then(|i| i.id) {
+                InvitationDAO::release(invitation_id).await?;
+            }
+            return Err(actix_web::error::ErrorInternalServerError(e));
+        }
+    };
+    if let Some(invitation_id) = invitation.as_ref().and_then(|i| i.id) {
+        InvitationDAO::add_user(invitation_id, inserted_user_id).await?;
+    }
 
-    data.root_dir_id = Some(root_dir_id);
-    UserDAO::update(&data).await?;
+    if !data.email_verified {
+        // the user can request another mail later, so a failing mail server does not block the registration
+        if let Err(e) = send_verification_mail(&data).await {
+            event!(
+                Level::ERROR,
+                "could not send verification mail to user {}: {}",
+                inserted_user_id,
+                e
+            );
+        }
+    }
 
     Ok(HttpResponse::Ok().json(inserted_user_id))
 }
+
+pub async fn verify_email(verify_data: web::Query<EmailVerify>) -> actix_web::Result<HttpResponse> {
+    let verification_token = VerificationTokenDAO::take_valid(
+        hash_opaque_token(verify_data.token.as_str()).as_str(),
+        VerificationPurpose::EmailVerification,
+    )
+    .await?
+    .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired verification token"))?;
+
+    let mut user = UserDAO::get(verification_token.user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("User does not exist anymore"))?;
+
+    // the token only proves access to the address it has been sent to
+    if user.email != verification_token.email {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The email address has changed since the token has been sent",
+        ));
+    }
+
+    user.email_verified = true;
+    UserDAO::update(&user).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub async fn resend_verification_email(
+    _authenticated: Authenticated<Claims>,
+) -> actix_web::Result<HttpResponse> {
+    let user = get_authenticated_user(&_authenticated).await?;
+
+    if user.email_verified {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The email address is already verified",
+        ));
+    }
+
+    send_verification_mail(&user).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Always answers with 200, so the endpoint cannot be used to find out which emails are registered.
+pub async fn request_password_reset(
+    request: HttpRequest,
+    reset_data: Json<PasswordResetRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let email = reset_data.into_inner().email;
+    let client_ip = throttling::get_client_ip(&request);
+    throttling::ensure_not_locked(Some(email.as_str()), client_ip.as_str()).await?;
+    // every request counts, so nobody can flood a mailbox with reset mails
+    throttling::register_failure(Some(email.as_str()), client_ip.as_str()).await?;
+
+    // the lookup and the mail happen in the background, so the response time does not tell
+    // whether the email is registered
+    actix_web::rt::spawn(async move {
+        if let Err(e) = send_password_reset_mail(email.as_str()).await {
+            event!(Level::ERROR, "could not send password reset mail: {}", e);
+        }
+    });
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn send_password_reset_mail(email: &str) -> actix_web::Result<()> {
+    // federated accounts sign in at their directory or identity provider, they get no local password
+    let user = match UserDAO::get_by_email(email)
+        .await?
+        .filter(|user| !user.is_federated())
+    {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let token = create_verification_token(&user, VerificationPurpose::PasswordReset).await?;
+
+    Mailer::send(
+        user.email.as_str(),
+        MailTemplate::PasswordReset {
+            firstname: user.firstname.to_owned(),
+            reset_token: token,
+            valid_minutes: VerificationPurpose::PasswordReset.get_ttl().whole_minutes(),
+        },
+    )
+    .await
+}
+
+pub async fn confirm_password_reset(
+    reset_data: Json<PasswordResetConfirm>,
+    invalidated_jwt_store: Data<InvalidatedJWTStore>,
+) -> actix_web::Result<HttpResponse> {
+    if !User::is_valid_hash_design(reset_data.pw_hash.as_str()) {
+        // not a hex encoded hash or less than 256 bit size
+        return Err(actix_web::error::ErrorExpectationFailed(
+            "Please provide at least a hex encoded sha256 hash",
+        ));
+    }
+
+    let reset_token = VerificationTokenDAO::take_valid(
+        hash_opaque_token(reset_data.token.as_str()).as_str(),
+        VerificationPurpose::PasswordReset,
+    )
+    .await?
+    .ok_or_else(|| actix_web::error::ErrorBadRequest("Invalid or expired reset token"))?;
+
+    let mut user = UserDAO::get(reset_token.user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("User does not exist anymore"))?;
+    // e.g. a token requested before the account has been linked
+    if user.is_federated() {
+        return Err(actix_web::error::ErrorForbidden(
+            "The password of accounts linked to an identity provider or a directory cannot be reset",
+        ));
+    }
+
+    user.pw_hash = User::hash_password(reset_data.pw_hash.as_str())?;
+    if user.email == reset_token.email {
+        // receiving the reset mail proves access to the address
+        user.email_verified = true;
+    }
+    UserDAO::update(&user).await?;
+
+    // a reset usually means the old password is compromised, so log out all devices
+    account::logout_everywhere(reset_token.user_id, &invalidated_jwt_store).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Replaces all previous tokens of the user for the purpose and returns the new plain token.
+async fn create_verification_token(
+    user: &User,
+    purpose: VerificationPurpose,
+) -> actix_web::Result<String> {
+    let user_id = user
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("User is missing id"))?;
+
+    VerificationTokenDAO::delete_for_user_and_purpose(user_id, purpose).await?;
+
+    let token = generate_opaque_token();
+    VerificationTokenDAO::insert(&mut VerificationToken::new(
+        user_id,
+        hash_opaque_token(token.as_str()),
+        purpose,
+        user.email.to_owned(),
+    ))
+    .await?;
+
+    Ok(token)
+}
+
+async fn send_verification_mail(user: &User) -> actix_web::Result<()> {
+    let token = create_verification_token(user, VerificationPurpose::EmailVerification).await?;
+
+    Mailer::send(
+        user.email.as_str(),
+        MailTemplate::EmailVerification {
+            firstname: user.firstname.to_owned(),
+            verification_link: format!(
+                "{}/v1/user/email/verify?token={}",
+                SETTINGS.get().unwrap().server.url.trim_end_matches('/'),
+                token
+            ),
+        },
+    )
+    .await
+}
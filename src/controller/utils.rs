@@ -4,6 +4,9 @@ use std::io;
 use std::str::FromStr;
 
 use crate::archive::ArchiveMethod;
+use crate::database::daos::user_dao::UserDAO;
+use crate::Claims;
+use actix_jwt_authc::Authenticated;
 use mongodb::bson::oid::ObjectId;
 
 pub fn extract_object_id(
@@ -41,3 +44,16 @@ pub fn get_archive_file_stream_http_response(
         ))
         .body(actix_web::body::BodyStream::new(rx)))
 }
+
+/// Users with an unverified email address are not allowed to share data or create api tokens.
+pub async fn ensure_verified_email(authenticated: &Authenticated<Claims>) -> actix_web::Result<()> {
+    match UserDAO::get_authenticated(authenticated).await? {
+        Some(user) if user.email_verified => Ok(()),
+        Some(_) => Err(actix_web::error::ErrorForbidden(
+            "Please verify your email address first",
+        )),
+        None => Err(actix_web::error::ErrorExpectationFailed(
+            "authenticated user not found in database",
+        )),
+    }
+}
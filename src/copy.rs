@@ -0,0 +1,173 @@
+//! Server-side copies of files and directory subtrees, so clients do not have to download and
+//! upload the data again. Every copy gets its own blob, the files can be changed independently.
+use std::collections::HashMap;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{DateTime, Uuid};
+use tracing::{event, Level};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::directory::Directory;
+use crate::database::entities::file::File;
+use crate::quota;
+use crate::storage::storage_provider::StorageProvider;
+
+/// Copies the file into the target directory, a name conflict is solved with "name (1).ext".
+pub async fn copy_file(file: &File, target: &Directory) -> actix_web::Result<File> {
+    let quota_bytes = get_quota_bytes(file.user_id).await?;
+    copy_file_with_quota(file, target, quota_bytes).await
+}
+
+/// Copies the directory with its subtree into the target directory, the files and directories
+/// in the trash are left out. A name conflict of the directory is solved with "name (1)".
+pub async fn copy_directory(dir: &Directory, target: &Directory) -> actix_web::Result<Directory> {
+    let target_id = target
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("directory id not found"))?;
+    let dir_id = dir
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("directory id not found"))?;
+    if DirectoryDAO::is_in_subtree_of(target, dir_id).await? {
+        return Err(actix_web::error::ErrorBadRequest(
+            "A directory cannot be copied into itself",
+        ));
+    }
+
+    // collect the subtree first, so the copies do not show up while walking it
+    let mut subtree: Vec<(Directory, Vec<File>)> = Vec::new();
+    let mut pending_dirs: Vec<Directory> = vec![dir.clone()];
+    while let Some(source) = pending_dirs.pop() {
+        pending_dirs.extend(DirectoryDAO::get_all_with_parent_id(source.id).await?);
+        let files = source.get_finished_files().await;
+        subtree.push((source, files));
+    }
+
+    // fail early, the quota is enforced when the blobs are copied
+    let user = UserDAO::get(dir.user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?;
+    let quota_bytes = user.get_quota_bytes();
+    let required_bytes: u64 = subtree
+        .iter()
+        .flat_map(|(_, files)| files.iter())
+        .map(|file| StorageProvider::get_file_size(file.uuid.clone()))
+        .sum();
+    if matches!(quota_bytes, Some(quota_bytes) if user.used_bytes + required_bytes as i64 > quota_bytes)
+    {
+        return Err(quota::get_quota_exceeded_error(quota_bytes));
+    }
+
+    let mut root_copy: Option<Directory> = None;
+    if let Err(e) = copy_subtree(subtree, target_id, quota_bytes, &mut root_copy).await {
+        // do not leave a partial copy behind, deleting it gives the reserved bytes back
+        if let Some(root_copy) = root_copy {
+            if let Err(delete_error) = DirectoryDAO::delete(&root_copy).await {
+                event!(
+                    Level::ERROR,
+                    "Failed to delete the partial copy of directory {}: {}",
+                    dir_id,
+                    delete_error
+                );
+            }
+        }
+        return Err(e);
+    }
+
+    root_copy.ok_or_else(|| actix_web::error::ErrorInternalServerError("copying directory failed"))
+}
+
+/// Creates the copies of the collected directories and their files below the target.
+async fn copy_subtree(
+    subtree: Vec<(Directory, Vec<File>)>,
+    target_id: ObjectId,
+    quota_bytes: Option<i64>,
+    root_copy: &mut Option<Directory>,
+) -> actix_web::Result<()> {
+    // the parents are collected before their children, so the copy of the parent always exists
+    let mut copied_dir_ids: HashMap<ObjectId, ObjectId> = HashMap::new();
+    for (source, files) in subtree {
+        let copied_parent_id = source
+            .parent_id
+            .and_then(|parent_id| copied_dir_ids.get(&parent_id).copied());
+        let (parent_id, name) = match copied_parent_id {
+            Some(copied_parent_id) => (copied_parent_id, source.name.clone()),
+            None => (
+                target_id,
+                DirectoryDAO::get_free_name(&source.name, target_id).await?,
+            ),
+        };
+
+        let mut copy = Directory {
+            id: None,
+            user_id: source.user_id,
+            parent_id: Some(parent_id),
+            name,
+            creation_date: DateTime::now(),
+            child_ids: vec![],
+            deletion_date: None,
+            trashed_with: None,
+        };
+        let copy_id = DirectoryDAO::insert(&mut copy).await?;
+        if root_copy.is_none() {
+            *root_copy = Some(copy.clone());
+        }
+
+        for file in files {
+            copy_file_with_quota(&file, &copy, quota_bytes).await?;
+        }
+        copied_dir_ids.insert(source.id.unwrap(), copy_id);
+    }
+    Ok(())
+}
+
+async fn get_quota_bytes(user_id: ObjectId) -> actix_web::Result<Option<i64>> {
+    Ok(UserDAO::get(user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorUnauthorized("User does not exist anymore"))?
+        .get_quota_bytes())
+}
+
+async fn copy_file_with_quota(
+    file: &File,
+    target: &Directory,
+    quota_bytes: Option<i64>,
+) -> actix_web::Result<File> {
+    let size = StorageProvider::get_file_size(file.uuid.clone());
+    quota::reserve(file.user_id, size, quota_bytes).await?;
+
+    let mut copy = File {
+        id: None,
+        parent_id: target
+            .id
+            .ok_or_else(|| actix_web::error::ErrorInternalServerError("directory id not found"))?,
+        user_id: file.user_id,
+        uuid: Uuid::new().to_string(),
+        hash: file.hash.to_owned(),
+        size: file.size,
+        mime: file.mime.to_owned(),
+        name: target.get_free_file_name(&file.name).await,
+        finished: true,
+        upload_length: None,
+        upload_expiration_date: None,
+        upload_lock: None,
+        upload_locked_until: None,
+        creation_date: DateTime::now(),
+        modification_date: file.modification_date,
+        deletion_date: None,
+        trashed_with: None,
+    };
+
+    if let Err(e) = StorageProvider::copy_file(file.uuid.clone(), copy.uuid.clone()).await {
+        UserDAO::release_used_bytes(file.user_id, size as i64).await?;
+        return Err(e);
+    }
+    if let Err(e) = FileDAO::insert(&mut copy).await {
+        StorageProvider::delete_file(copy.uuid.clone())?;
+        UserDAO::release_used_bytes(file.user_id, size as i64).await?;
+        return Err(e);
+    }
+    Ok(copy)
+}
@@ -0,0 +1,170 @@
+use std::borrow::Borrow;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::api_token::ApiToken;
+
+pub struct ApiTokenDAO {}
+
+#[async_trait]
+impl DAO<ApiToken, ObjectId> for ApiTokenDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<ApiToken>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<ApiToken>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(api_token: &mut ApiToken) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(api_token.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        api_token.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = api_token.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "api token insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(api_token: &ApiToken) -> actix_web::Result<u64> {
+        if let Some(id) = api_token.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "name": api_token.name.to_owned(),
+                            "expiration_date": api_token.expiration_date,
+                            "last_used_date": api_token.last_used_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "api token id not found",
+        ))
+    }
+
+    async fn delete(api_token: &ApiToken) -> actix_web::Result<u64> {
+        if let Some(id) = api_token.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "api token id not found",
+        ))
+    }
+}
+
+// custom methods
+impl ApiTokenDAO {
+    pub async fn get_by_token_hash(token_hash: &str) -> actix_web::Result<Option<ApiToken>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "token_hash": token_hash
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    pub async fn get_all_for_user(user_id: ObjectId) -> actix_web::Result<Vec<ApiToken>> {
+        let mut api_tokens: Vec<ApiToken> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "user_id": user_id,
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(api_token) = cursor.next().await {
+            if let Ok(api_token) = api_token {
+                api_tokens.push(api_token);
+            }
+        }
+
+        Ok(api_tokens)
+    }
+
+    pub async fn register_usage(api_token: &mut ApiToken) -> actix_web::Result<()> {
+        api_token.last_used_date = Some(DateTime::now());
+        Self::update(api_token).await?;
+        Ok(())
+    }
+
+    /// Removes all api tokens of the user, e.g. when the account is deleted.
+    pub async fn delete_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+}
@@ -4,20 +4,26 @@ use actix_jwt_authc::Authenticated;
 use async_trait::async_trait;
 use futures::StreamExt;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, DateTime};
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::error::{ErrorKind, TRANSIENT_TRANSACTION_ERROR};
+use mongodb::{ClientSession, Collection};
 use tracing::{event, Level};
 
 use crate::database::daos::dao::DAO;
 use crate::database::daos::file_dao::FileDAO;
 use crate::database::daos::share_dao::ShareDAO;
 use crate::database::daos::syncstate_dao::SyncStateDAO;
+use crate::database::database;
 use crate::database::entities::directory::{Directory, DirectoryGetResponseObject};
 use crate::database::entities::syncstate::{SyncState, SyncStateAction, SyncStateType};
 use crate::jwt_utils::extract_user_oid;
-use crate::storage::storage_provider::StorageProvider;
+use crate::quota;
 use crate::Claims;
 
 static ROOT_DIR_NAME: &str = "/";
+// returned by a standalone server for operations in a transaction
+const ILLEGAL_OPERATION_CODE: i32 = 20;
+const MOVE_TRANSACTION_ATTEMPTS: u32 = 3;
 
 pub struct DirectoryDAO {}
 
@@ -45,7 +51,8 @@ impl DAO<Directory, ObjectId> for DirectoryDAO {
             .find_one(
                 doc! {
                     "_id": oid,
-                    "user_id": user_id
+                    "user_id": user_id,
+                    "deletion_date": null
                 },
                 None,
             )
@@ -118,39 +125,68 @@ impl DAO<Directory, ObjectId> for DirectoryDAO {
         ))
     }
 
+    /// Deletes the directory with its whole subtree, including the files and directories in the trash.
+    /// The deepest directories are deleted first, so an interrupted deletion leaves a connected
+    /// subtree behind, that can simply be deleted again.
     async fn delete(dir: &Directory) -> actix_web::Result<u64> {
         if let Some(id) = dir.id {
-            for file in dir.get_files().await {
-                StorageProvider::delete_file(file.uuid.clone())?;
-                FileDAO::delete(&file).await?;
+            let mut subtree: Vec<Directory> = vec![dir.clone()];
+            let mut index = 0;
+            while index < subtree.len() {
+                if let Some(node_id) = subtree[index].id {
+                    subtree.extend(Self::get_all_children(node_id).await?);
+                }
+                index += 1;
             }
 
-            ShareDAO::delete_for_corresponding_id(id).await?;
-
-            let delete_result = Self::get_collection()
-                .await
-                .delete_one(
-                    doc! {
-                        "_id": id
-                    },
-                    None,
-                )
-                .await
-                .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
-            SyncStateDAO::delete_for_corresponding_id(id).await?;
-            SyncStateDAO::delete_for_corresponding_parent_id(id).await?;
+            // forget the previous changes below the subtree, the deletions are emitted afterwards
+            for node in &subtree {
+                SyncStateDAO::delete_for_corresponding_parent_id(node.id.unwrap()).await?;
+            }
 
-            let _ = SyncStateDAO::insert(&mut SyncState::new(
-                SyncStateType::Directory,
-                SyncStateAction::Delete,
-                id,
-                dir.parent_id,
-                dir.user_id,
-            ))
-            .await?;
+            let mut deleted_count = 0;
+            for node in subtree.iter().rev() {
+                let node_id = node.id.unwrap();
+
+                let files = FileDAO::get_files_by_parent_id(node_id).await?;
+                for file in files {
+                    quota::delete_file_blob(&file).await?;
+                    FileDAO::delete(&file).await?;
+                }
+
+                ShareDAO::delete_for_corresponding_id(node_id).await?;
+
+                if node_id == id {
+                    if let Some(parent_id) = dir.parent_id {
+                        Self::remove_child(parent_id, id).await?;
+                    }
+                }
+
+                let delete_result = Self::get_collection()
+                    .await
+                    .delete_one(
+                        doc! {
+                            "_id": node_id
+                        },
+                        None,
+                    )
+                    .await
+                    .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+                deleted_count += delete_result.deleted_count;
+
+                SyncStateDAO::delete_for_corresponding_id(node_id).await?;
+
+                let _ = SyncStateDAO::insert(&mut SyncState::new(
+                    SyncStateType::Directory,
+                    SyncStateAction::Delete,
+                    node_id,
+                    node.parent_id,
+                    node.user_id,
+                ))
+                .await?;
+            }
 
-            return Ok(delete_result.deleted_count);
+            return Ok(deleted_count);
         }
 
         Err(actix_web::error::ErrorInternalServerError(
@@ -167,7 +203,8 @@ impl DirectoryDAO {
             .await
             .find(
                 doc! {
-                    "parent_id": parent_id
+                    "parent_id": parent_id,
+                    "deletion_date": null
                 },
                 None,
             )
@@ -183,6 +220,48 @@ impl DirectoryDAO {
         Ok(dirs)
     }
 
+    /// Returns the child directories, including the ones in the trash.
+    async fn get_all_children(parent_id: ObjectId) -> actix_web::Result<Vec<Directory>> {
+        let mut cursor = DirectoryDAO::get_collection()
+            .await
+            .find(
+                doc! {
+                    "parent_id": parent_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let mut dirs: Vec<Directory> = vec![];
+        while let Some(dir) = cursor.next().await {
+            if let Ok(dir) = dir {
+                dirs.push(dir);
+            }
+        }
+        Ok(dirs)
+    }
+
+    /// Removes the child id from the parent, no matter if the parent is in the trash.
+    async fn remove_child(parent_oid: ObjectId, child_oid: ObjectId) -> actix_web::Result<()> {
+        DirectoryDAO::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": parent_oid
+                },
+                doc! {
+                    "$pull": {
+                        "child_ids": child_oid
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
     pub async fn get_all_with_parent_id_for_response(
         parent_id: Option<ObjectId>,
     ) -> actix_web::Result<Vec<DirectoryGetResponseObject>> {
@@ -191,7 +270,7 @@ impl DirectoryDAO {
             dir_names.push(DirectoryGetResponseObject {
                 id: dir.id.unwrap(),
                 name: dir.name,
-                child_dir_count: dir.child_ids.len() as u64,
+                child_dir_count: Self::count_with_parent_id(dir.id.unwrap()).await?,
                 child_file_count: FileDAO::count_files_by_parent_id(dir.id.unwrap()).await?,
                 creation_date_ts: dir.creation_date.timestamp_millis(),
             });
@@ -208,7 +287,8 @@ impl DirectoryDAO {
             .find_one(
                 doc! {
                     "name": name,
-                    "parent_id": parent_id
+                    "parent_id": parent_id,
+                    "deletion_date": null
                 },
                 None,
             )
@@ -250,6 +330,8 @@ impl DirectoryDAO {
             name: ROOT_DIR_NAME.parse()?,
             creation_date: DateTime::now(),
             child_ids: vec![],
+            deletion_date: None,
+            trashed_with: None,
         };
 
         Ok(DirectoryDAO::insert(&mut new_dir)
@@ -279,33 +361,6 @@ impl DirectoryDAO {
         ))
     }
 
-    async fn remove_child_by_oid(
-        parent_oid: ObjectId,
-        child_oid: ObjectId,
-        user_id: ObjectId,
-    ) -> actix_web::Result<()> {
-        let parent = DirectoryDAO::get_with_user(parent_oid, user_id).await?;
-        if let Some(mut parent) = parent {
-            let index = parent
-                .child_ids
-                .iter()
-                .position(|x| *x == child_oid)
-                .unwrap();
-            parent.child_ids.remove(index);
-            if DirectoryDAO::update(&parent).await? > 0 {
-                return Ok(());
-            }
-            event!(
-                Level::INFO,
-                "error removing child from directory {}?",
-                parent_oid
-            );
-        }
-        Err(actix_web::error::ErrorNotFound(
-            "could not get parent directory",
-        ))
-    }
-
     pub async fn has_user_permission(
         directory_id: ObjectId,
         user_id: ObjectId,
@@ -356,24 +411,19 @@ impl DirectoryDAO {
     ) -> actix_web::Result<()> {
         if let (Some(id), Some(parent_id)) = (dir.id, dir.parent_id) {
             // do not move if parent_id and new_parent_id are equal or if someone tries to move root
-            // todo: does this check really work?
             if parent_id == new_parent_oid {
                 return Err(actix_web::error::ErrorInternalServerError(
                     "moving from current parent to current parent is not allowed",
                 ));
-            } else if id == new_parent_oid {
-                return Err(actix_web::error::ErrorInternalServerError(
-                    "moving directory into it self is not allowed",
-                ));
             } else if id == _authenticated.claims.thunder_root_dir_id {
                 return Err(actix_web::error::ErrorInternalServerError(
                     "moving user root directory is not allowed",
                 ));
             }
 
-            DirectoryDAO::has_user_permission(new_parent_oid, extract_user_oid(_authenticated))
-                .await
-                .map_err(|_| {
+            DirectoryDAO::get_with_user(new_parent_oid, extract_user_oid(_authenticated))
+                .await?
+                .ok_or_else(|| {
                     actix_web::error::ErrorForbidden(
                         "no permission to access the requested parent directory",
                     )
@@ -385,28 +435,9 @@ impl DirectoryDAO {
                 ));
             }
 
-            // give dir the new parent id
-            DirectoryDAO::get_collection()
-                .await
-                .update_one(
-                    doc! {
-                        "_id": id
-                    },
-                    doc! {
-                        "$set": {
-                            "parent_id": new_parent_oid
-                        }
-                    },
-                    None,
-                )
-                .await
-                .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
-            // add dir as child id to the new parent
-            DirectoryDAO::add_child_by_oid(new_parent_oid, id, dir.user_id).await?;
-
-            // remove child id from old parent
-            DirectoryDAO::remove_child_by_oid(parent_id, id, dir.user_id).await?;
+            // the new parent must not be the directory itself or one of its descendants,
+            // otherwise the subtree would be detached from the root, this is checked in the move
+            Self::apply_move(id, Some(parent_id), new_parent_oid).await?;
 
             let _ = SyncStateDAO::insert(&mut SyncState::new(
                 SyncStateType::Directory,
@@ -424,4 +455,362 @@ impl DirectoryDAO {
             "no permission or directory does not exist",
         ))
     }
+
+    /// Returns the first of "name", "name (1)", ..., that is not used in the parent directory yet.
+    pub async fn get_free_name(name: &str, parent_id: ObjectId) -> actix_web::Result<String> {
+        let mut candidate = name.to_string();
+        let mut counter = 1;
+        while Self::dir_by_name_exists_in(&candidate, parent_id).await? {
+            candidate = format!("{} ({})", name, counter);
+            counter += 1;
+        }
+        Ok(candidate)
+    }
+
+    /// Returns the ancestors of the directory, starting with its parent and ending with the root.
+    pub async fn get_ancestors(dir: &Directory) -> actix_web::Result<Vec<Directory>> {
+        let mut ancestors: Vec<Directory> = Vec::new();
+        let mut parent_id = dir.parent_id;
+
+        while let Some(id) = parent_id {
+            // a corrupted tree must not lead to an endless loop
+            if Some(id) == dir.id || ancestors.iter().any(|ancestor| ancestor.id == Some(id)) {
+                return Err(actix_web::error::ErrorInternalServerError(
+                    "directory tree contains a cycle",
+                ));
+            }
+
+            let ancestor = Self::get(id)
+                .await?
+                .ok_or_else(|| actix_web::error::ErrorInternalServerError("parent not found"))?;
+            parent_id = ancestor.parent_id;
+            ancestors.push(ancestor);
+        }
+
+        Ok(ancestors)
+    }
+
+    /// Checks, if the directory is the given ancestor or one of its descendants.
+    pub async fn is_in_subtree_of(
+        dir: &Directory,
+        ancestor_id: ObjectId,
+    ) -> actix_web::Result<bool> {
+        if dir.id == Some(ancestor_id) {
+            return Ok(true);
+        }
+        Ok(Self::get_ancestors(dir)
+            .await?
+            .iter()
+            .any(|ancestor| ancestor.id == Some(ancestor_id)))
+    }
+
+    /// Counts the child directories, that are not in the trash.
+    pub async fn count_with_parent_id(parent_id: ObjectId) -> actix_web::Result<u64> {
+        Self::get_collection()
+            .await
+            .count_documents(
+                doc! {
+                    "parent_id": parent_id,
+                    "deletion_date": null
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Moves the directory to the trash, `None` restores it again.
+    pub async fn set_deletion_date(
+        id: ObjectId,
+        deletion_date: Option<DateTime>,
+        trashed_with: Option<ObjectId>,
+    ) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": id
+                },
+                doc! {
+                    "$set": {
+                        "deletion_date": deletion_date,
+                        "trashed_with": trashed_with,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Restores the directories, that have been moved to the trash together with an ancestor.
+    pub async fn restore_trashed_with(trashed_with: ObjectId) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_many(
+                doc! {
+                    "trashed_with": trashed_with
+                },
+                doc! {
+                    "$set": {
+                        "deletion_date": null,
+                        "trashed_with": null,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Moves the directory to another parent, e.g. when it is restored and the old parent is gone.
+    pub async fn set_parent(
+        dir: &mut Directory,
+        new_parent_oid: ObjectId,
+    ) -> actix_web::Result<()> {
+        let id = dir
+            .id
+            .ok_or_else(|| actix_web::error::ErrorInternalServerError("directory id not found"))?;
+        Self::apply_move(id, dir.parent_id, new_parent_oid).await?;
+
+        dir.parent_id = Some(new_parent_oid);
+        DirectoryDAO::update(dir).await?;
+        Ok(())
+    }
+
+    /// Sets the new parent id and moves the child id from the old to the new parent in one
+    /// transaction, so a failure cannot leave the `child_ids` inconsistent.
+    /// Transactions need MongoDB to run as a replica set, on a standalone server moves fail.
+    async fn apply_move(
+        id: ObjectId,
+        old_parent_oid: Option<ObjectId>,
+        new_parent_oid: ObjectId,
+    ) -> actix_web::Result<()> {
+        let client = database::establish_client().await.ok_or_else(|| {
+            actix_web::error::ErrorInternalServerError("could not connect to the database")
+        })?;
+        let collection = database::get_collection_of_client::<Directory>(&client);
+        let mut session = client.start_session(None).await.map_err(get_move_error)?;
+
+        let mut attempt = 1;
+        loop {
+            session
+                .start_transaction(None)
+                .await
+                .map_err(get_move_error)?;
+
+            let result = match Self::apply_move_in_session(
+                &collection,
+                &mut session,
+                id,
+                old_parent_oid,
+                new_parent_oid,
+            )
+            .await
+            {
+                Ok(Ok(())) => session.commit_transaction().await.map(Ok),
+                result => {
+                    let _ = session.abort_transaction().await;
+                    result
+                }
+            };
+
+            match result {
+                Ok(result) => return result,
+                // a concurrent move changed one of the directories
+                Err(e)
+                    if e.contains_label(TRANSIENT_TRANSACTION_ERROR)
+                        && attempt < MOVE_TRANSACTION_ATTEMPTS =>
+                {
+                    attempt += 1;
+                }
+                Err(e) => return Err(get_move_error(e)),
+            }
+        }
+    }
+
+    /// The new parent and its ancestors are checked inside the transaction and get a guard write,
+    /// so a concurrent move of one of them conflicts with this move instead of creating a cycle.
+    async fn apply_move_in_session(
+        collection: &Collection<Directory>,
+        session: &mut ClientSession,
+        id: ObjectId,
+        old_parent_oid: Option<ObjectId>,
+        new_parent_oid: ObjectId,
+    ) -> mongodb::error::Result<actix_web::Result<()>> {
+        let mut ancestor_ids: Vec<ObjectId> = Vec::new();
+        let mut ancestor_id = Some(new_parent_oid);
+        while let Some(current_id) = ancestor_id {
+            if current_id == id {
+                return Ok(Err(actix_web::error::ErrorBadRequest(
+                    "moving a directory into itself or one of its subdirectories is not allowed",
+                )));
+            }
+            // a corrupted tree must not lead to an endless loop
+            if ancestor_ids.contains(&current_id) {
+                return Ok(Err(actix_web::error::ErrorInternalServerError(
+                    "directory tree contains a cycle",
+                )));
+            }
+
+            let ancestor = match collection
+                .find_one_with_session(doc! { "_id": current_id }, None, session)
+                .await?
+            {
+                Some(ancestor) => ancestor,
+                None => {
+                    return Ok(Err(actix_web::error::ErrorInternalServerError(
+                        "parent not found",
+                    )))
+                }
+            };
+            // unchanged documents are no writes, so the counter has to change
+            collection
+                .update_one_with_session(
+                    doc! { "_id": current_id },
+                    doc! { "$inc": { "move_guard": 1 } },
+                    None,
+                    session,
+                )
+                .await?;
+
+            ancestor_ids.push(current_id);
+            ancestor_id = ancestor.parent_id;
+        }
+
+        collection
+            .update_one_with_session(
+                doc! { "_id": id },
+                doc! { "$set": { "parent_id": new_parent_oid } },
+                None,
+                session,
+            )
+            .await?;
+        collection
+            .update_one_with_session(
+                doc! { "_id": new_parent_oid },
+                doc! { "$addToSet": { "child_ids": id } },
+                None,
+                session,
+            )
+            .await?;
+        if let Some(old_parent_oid) = old_parent_oid {
+            collection
+                .update_one_with_session(
+                    doc! { "_id": old_parent_oid },
+                    doc! { "$pull": { "child_ids": id } },
+                    None,
+                    session,
+                )
+                .await?;
+        }
+        Ok(Ok(()))
+    }
+
+    /// Returns the directories of the user, that have been moved to the trash on their own.
+    pub async fn get_trashed_for_user(user_id: ObjectId) -> actix_web::Result<Vec<Directory>> {
+        Self::find_trashed(doc! {
+            "user_id": user_id,
+            "deletion_date": {"$ne": null},
+            "trashed_with": null
+        })
+        .await
+    }
+
+    pub async fn get_trashed_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<Directory>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id,
+                    "deletion_date": {"$ne": null},
+                    "trashed_with": null
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Returns the directories of all users, that have been moved to the trash on their own before the date.
+    pub async fn get_trashed_before(date: DateTime) -> actix_web::Result<Vec<Directory>> {
+        Self::find_trashed(doc! {
+            "deletion_date": {"$lt": date},
+            "trashed_with": null
+        })
+        .await
+    }
+
+    async fn find_trashed(filter: Document) -> actix_web::Result<Vec<Directory>> {
+        let mut dirs: Vec<Directory> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(filter, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(dir) = cursor.next().await {
+            if let Ok(dir) = dir {
+                dirs.push(dir);
+            }
+        }
+
+        Ok(dirs)
+    }
+
+    pub async fn count_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        Self::get_collection()
+            .await
+            .count_documents(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Removes all directories of the user, e.g. when the account is deleted.
+    pub async fn delete_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+}
+
+fn get_move_error(error: mongodb::error::Error) -> actix_web::Error {
+    if matches!(&*error.kind, ErrorKind::Command(command_error) if command_error.code == ILLEGAL_OPERATION_CODE)
+    {
+        event!(
+            Level::ERROR,
+            "moving directories needs transactions, MongoDB has to run as a replica set: {}",
+            error
+        );
+        return actix_web::error::ErrorInternalServerError(
+            "Moving directories needs MongoDB to run as a replica set",
+        );
+    }
+    if error.contains_label(TRANSIENT_TRANSACTION_ERROR) {
+        return actix_web::error::ErrorConflict(
+            "The directories have been changed concurrently, please try again",
+        );
+    }
+    actix_web::error::ErrorInternalServerError(error)
 }
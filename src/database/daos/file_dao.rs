@@ -3,9 +3,10 @@ use std::borrow::Borrow;
 use async_trait::async_trait;
 use futures::StreamExt;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, DateTime};
+use mongodb::bson::{doc, DateTime, Document};
 
 use crate::database::daos::dao::DAO;
+use crate::database::daos::file_version_dao::FileVersionDAO;
 use crate::database::daos::share_dao::ShareDAO;
 use crate::database::daos::syncstate_dao::SyncStateDAO;
 use crate::database::entities::file::File;
@@ -34,7 +35,8 @@ impl DAO<File, ObjectId> for FileDAO {
             .find_one(
                 doc! {
                     "_id": oid,
-                    "user_id": user_id
+                    "user_id": user_id,
+                    "deletion_date": null
                 },
                 None,
             )
@@ -52,14 +54,10 @@ impl DAO<File, ObjectId> for FileDAO {
         file.id = insert_result.inserted_id.as_object_id();
 
         if let Some(id) = file.id {
-            let _ = SyncStateDAO::insert(&mut SyncState::new(
-                SyncStateType::File,
-                SyncStateAction::Create,
-                id,
-                Some(file.parent_id),
-                file.user_id,
-            ))
-            .await?;
+            // clients learn about resumable uploads once they are finished
+            if file.finished {
+                Self::insert_create_sync_state(file).await?;
+            }
 
             return Ok(id);
         }
@@ -83,10 +81,14 @@ impl DAO<File, ObjectId> for FileDAO {
                             "user_id": file.user_id.to_owned(),
                             "uuid": file.uuid.to_owned(),
                             "hash": file.hash.to_owned(),
+                            "size": file.size,
                             "mime": file.mime.to_owned(),
                             "name": file.name.to_owned(),
                             "finished": file.finished.to_owned(),
+                            "upload_length": file.upload_length,
+                            "upload_expiration_date": file.upload_expiration_date,
                             "creation_date": DateTime::now(),
+                            "modification_date": file.modification_date,
                         }
                     },
                     None,
@@ -116,15 +118,18 @@ impl DAO<File, ObjectId> for FileDAO {
                 .await
                 .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
 
+            FileVersionDAO::delete_all_for_file(id).await?;
             SyncStateDAO::delete_for_corresponding_id(id).await?;
-            let _ = SyncStateDAO::insert(&mut SyncState::new(
-                SyncStateType::File,
-                SyncStateAction::Delete,
-                id,
-                Some(file.parent_id),
-                file.user_id,
-            ))
-            .await?;
+            if file.finished {
+                let _ = SyncStateDAO::insert(&mut SyncState::new(
+                    SyncStateType::File,
+                    SyncStateAction::Delete,
+                    id,
+                    Some(file.parent_id),
+                    file.user_id,
+                ))
+                .await?;
+            }
 
             return Ok(delete_result.deleted_count);
         }
@@ -159,13 +164,132 @@ impl FileDAO {
             .find_one(
                 doc! {
                     "uuid": uuid,
-                    "user_id": user_id
+                    "user_id": user_id,
+                    "finished": true,
+                    "deletion_date": null
                 },
                 None,
             )
             .await
             .map_err(|e| actix_web::error::ErrorInternalServerError(e))
     }
+    /// Returns the file of a resumable upload, no matter if it is finished or not.
+    pub async fn get_upload_by_uuid_for_user(
+        uuid: &str,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<File>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "uuid": uuid,
+                    "user_id": user_id,
+                    "upload_length": {"$ne": null},
+                    "deletion_date": null
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+    /// Returns the file of a resumable upload, that has not received all of its data yet.
+    pub async fn get_unfinished_upload_by_uuid_for_user(
+        uuid: &str,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<File>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "uuid": uuid,
+                    "user_id": user_id,
+                    "finished": false,
+                    "deletion_date": null
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+    /// Locks the unfinished upload, if it is not locked by another request and has still received
+    /// `size` bytes. The lock is stored in the database, so it works across all instances.
+    pub async fn lock_upload(
+        uuid: &str,
+        size: i64,
+        lock: &str,
+        locked_until: DateTime,
+    ) -> actix_web::Result<bool> {
+        let update_result = Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "uuid": uuid,
+                    "finished": false,
+                    "size": size,
+                    "$or": [
+                        {"upload_locked_until": null},
+                        {"upload_locked_until": {"$lt": DateTime::now()}}
+                    ]
+                },
+                doc! {
+                    "$set": {
+                        "upload_lock": lock,
+                        "upload_locked_until": locked_until
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(update_result.matched_count == 1)
+    }
+    /// Extends the lock, fails if the lock has expired and has been taken by another request.
+    pub async fn renew_upload_lock(
+        uuid: &str,
+        lock: &str,
+        locked_until: DateTime,
+    ) -> actix_web::Result<bool> {
+        let update_result = Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "uuid": uuid,
+                    "upload_lock": lock
+                },
+                doc! {
+                    "$set": {
+                        "upload_locked_until": locked_until
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(update_result.matched_count == 1)
+    }
+    pub async fn unlock_upload(uuid: &str, lock: &str) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "uuid": uuid,
+                    "upload_lock": lock
+                },
+                doc! {
+                    "$set": {
+                        "upload_lock": null,
+                        "upload_locked_until": null
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+    /// Returns all files of the directory, including unfinished uploads and files in the trash.
     pub async fn get_files_by_parent_id(parent_id: ObjectId) -> actix_web::Result<Vec<File>> {
         let mut files: Vec<File> = Vec::new();
 
@@ -193,11 +317,221 @@ impl FileDAO {
             .await
             .count_documents(
                 doc! {
-                    "parent_id": parent_id
+                    "parent_id": parent_id,
+                    "finished": true,
+                    "deletion_date": null
                 },
                 None,
             )
             .await
             .map_err(|_| actix_web::error::ErrorNotFound("counting files by parent_id failed"))?)
     }
+
+    pub async fn get_all_for_user(user_id: ObjectId) -> actix_web::Result<Vec<File>> {
+        let mut files: Vec<File> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(file) = cursor.next().await {
+            if let Ok(file) = file {
+                files.push(file);
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Removes all files of the user, e.g. when the account is deleted.
+    pub async fn delete_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+
+    /// Returns the resumable uploads, that have not been finished in time.
+    pub async fn get_expired_uploads() -> actix_web::Result<Vec<File>> {
+        let mut files: Vec<File> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "finished": false,
+                    "upload_expiration_date": {"$lt": DateTime::now()}
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(file) = cursor.next().await {
+            if let Ok(file) = file {
+                files.push(file);
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Moves the file to the trash, `None` restores it again.
+    pub async fn set_deletion_date(
+        id: ObjectId,
+        deletion_date: Option<DateTime>,
+        trashed_with: Option<ObjectId>,
+    ) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": id
+                },
+                doc! {
+                    "$set": {
+                        "deletion_date": deletion_date,
+                        "trashed_with": trashed_with,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Moves the files of the directory, that are not in the trash yet, to the trash.
+    pub async fn trash_by_parent_id(
+        parent_id: ObjectId,
+        deletion_date: DateTime,
+        trashed_with: ObjectId,
+    ) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_many(
+                doc! {
+                    "parent_id": parent_id,
+                    "deletion_date": null
+                },
+                doc! {
+                    "$set": {
+                        "deletion_date": deletion_date,
+                        "trashed_with": trashed_with,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Restores the files, that have been moved to the trash together with the directory.
+    pub async fn restore_trashed_with(trashed_with: ObjectId) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_many(
+                doc! {
+                    "trashed_with": trashed_with
+                },
+                doc! {
+                    "$set": {
+                        "deletion_date": null,
+                        "trashed_with": null,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Returns the files of the user, that have been moved to the trash on their own.
+    pub async fn get_trashed_for_user(user_id: ObjectId) -> actix_web::Result<Vec<File>> {
+        Self::find_trashed(doc! {
+            "user_id": user_id,
+            "deletion_date": {"$ne": null},
+            "trashed_with": null
+        })
+        .await
+    }
+
+    pub async fn get_trashed_by_uuid_for_user(
+        uuid: &str,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<File>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "uuid": uuid,
+                    "user_id": user_id,
+                    "deletion_date": {"$ne": null},
+                    "trashed_with": null
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Returns the files of all users, that have been moved to the trash on their own before the date.
+    pub async fn get_trashed_before(date: DateTime) -> actix_web::Result<Vec<File>> {
+        Self::find_trashed(doc! {
+            "deletion_date": {"$lt": date},
+            "trashed_with": null
+        })
+        .await
+    }
+
+    async fn find_trashed(filter: Document) -> actix_web::Result<Vec<File>> {
+        let mut files: Vec<File> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(filter, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(file) = cursor.next().await {
+            if let Ok(file) = file {
+                files.push(file);
+            }
+        }
+
+        Ok(files)
+    }
+
+    pub async fn insert_create_sync_state(file: &File) -> actix_web::Result<()> {
+        let id = file
+            .id
+            .ok_or_else(|| actix_web::error::ErrorInternalServerError("file id not found"))?;
+
+        let _ = SyncStateDAO::insert(&mut SyncState::new(
+            SyncStateType::File,
+            SyncStateAction::Create,
+            id,
+            Some(file.parent_id),
+            file.user_id,
+        ))
+        .await?;
+        Ok(())
+    }
 }
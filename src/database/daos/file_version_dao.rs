@@ -0,0 +1,222 @@
+use std::borrow::Borrow;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOptions;
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::file_version::FileVersion;
+
+pub struct FileVersionDAO {}
+
+#[async_trait]
+impl DAO<FileVersion, ObjectId> for FileVersionDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<FileVersion>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<FileVersion>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(file_version: &mut FileVersion) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(file_version.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        file_version.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = file_version.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "file version insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(file_version: &FileVersion) -> actix_web::Result<u64> {
+        if let Some(id) = file_version.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "file_id": file_version.file_id,
+                            "uuid": file_version.uuid.to_owned(),
+                            "hash": file_version.hash.to_owned(),
+                            "size": file_version.size,
+                            "mime": file_version.mime.to_owned(),
+                            "creation_date": file_version.creation_date,
+                            "modification_date": file_version.modification_date,
+                            "replaced_date": file_version.replaced_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "file version id not found",
+        ))
+    }
+
+    async fn delete(file_version: &FileVersion) -> actix_web::Result<u64> {
+        if let Some(id) = file_version.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "file version id not found",
+        ))
+    }
+}
+
+// custom methods
+impl FileVersionDAO {
+    /// Returns the kept versions of the file, the most recently replaced first.
+    pub async fn get_all_for_file(file_id: ObjectId) -> actix_web::Result<Vec<FileVersion>> {
+        let mut file_versions: Vec<FileVersion> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "file_id": file_id,
+                },
+                FindOptions::builder()
+                    .sort(doc! { "replaced_date": -1 })
+                    .build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(file_version) = cursor.next().await {
+            if let Ok(file_version) = file_version {
+                file_versions.push(file_version);
+            }
+        }
+
+        Ok(file_versions)
+    }
+
+    /// Returns the versions of all users, that have been replaced before the given date.
+    pub async fn get_replaced_before(date: DateTime) -> actix_web::Result<Vec<FileVersion>> {
+        let mut file_versions: Vec<FileVersion> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "replaced_date": {"$lt": date},
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(file_version) = cursor.next().await {
+            if let Ok(file_version) = file_version {
+                file_versions.push(file_version);
+            }
+        }
+
+        Ok(file_versions)
+    }
+
+    pub async fn get_all_for_user(user_id: ObjectId) -> actix_web::Result<Vec<FileVersion>> {
+        let mut file_versions: Vec<FileVersion> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "user_id": user_id,
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(file_version) = cursor.next().await {
+            if let Ok(file_version) = file_version {
+                file_versions.push(file_version);
+            }
+        }
+
+        Ok(file_versions)
+    }
+
+    pub async fn delete_all_for_file(file_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "file_id": file_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+
+    /// Removes all versions of the user, e.g. when the account is deleted.
+    pub async fn delete_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+}
@@ -0,0 +1,160 @@
+use std::borrow::Borrow;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::IndexOptions;
+use mongodb::IndexModel;
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::invalidated_jwt::InvalidatedJWT;
+
+pub struct InvalidatedJWTDAO {}
+
+#[async_trait]
+impl DAO<InvalidatedJWT, ObjectId> for InvalidatedJWTDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<InvalidatedJWT>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<InvalidatedJWT>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(invalidated_jwt: &mut InvalidatedJWT) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(invalidated_jwt.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        invalidated_jwt.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = invalidated_jwt.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "invalidated jwt insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(invalidated_jwt: &InvalidatedJWT) -> actix_web::Result<u64> {
+        if let Some(id) = invalidated_jwt.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "expiration_date": invalidated_jwt.expiration_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "invalidated jwt id not found",
+        ))
+    }
+
+    async fn delete(invalidated_jwt: &InvalidatedJWT) -> actix_web::Result<u64> {
+        if let Some(id) = invalidated_jwt.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "invalidated jwt id not found",
+        ))
+    }
+}
+
+// custom methods
+impl InvalidatedJWTDAO {
+    /// Lets MongoDB remove invalidated tokens as soon as they would have expired anyway.
+    pub async fn create_expiration_index() -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {
+                        "expiration_date": 1
+                    })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Returns all tokens that have been invalidated since the given date and are not expired yet.
+    pub async fn get_created_since(since: DateTime) -> actix_web::Result<Vec<InvalidatedJWT>> {
+        let mut invalidated_jwts: Vec<InvalidatedJWT> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "creation_date": {"$gte": since},
+                    "expiration_date": {"$gt": DateTime::now()},
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(invalidated_jwt) = cursor.next().await {
+            if let Ok(invalidated_jwt) = invalidated_jwt {
+                invalidated_jwts.push(invalidated_jwt);
+            }
+        }
+
+        Ok(invalidated_jwts)
+    }
+}
@@ -0,0 +1,196 @@
+use std::borrow::Borrow;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::invitation::Invitation;
+
+pub struct InvitationDAO {}
+
+#[async_trait]
+impl DAO<Invitation, ObjectId> for InvitationDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<Invitation>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<Invitation>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "created_by": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(invitation: &mut Invitation) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(invitation.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        invitation.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = invitation.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "invitation insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(invitation: &Invitation) -> actix_web::Result<u64> {
+        if let Some(id) = invitation.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "email": invitation.email.to_owned(),
+                            "max_uses": invitation.max_uses,
+                            "use_count": invitation.use_count,
+                            "used_by": invitation.used_by.to_owned(),
+                            "expiration_date": invitation.expiration_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "invitation id not found",
+        ))
+    }
+
+    async fn delete(invitation: &Invitation) -> actix_web::Result<u64> {
+        if let Some(id) = invitation.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "invitation id not found",
+        ))
+    }
+}
+
+// custom methods
+impl InvitationDAO {
+    pub async fn get_all() -> actix_web::Result<Vec<Invitation>> {
+        let mut invitations: Vec<Invitation> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(doc! {}, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(invitation) = cursor.next().await {
+            if let Ok(invitation) = invitation {
+                invitations.push(invitation);
+            }
+        }
+
+        Ok(invitations)
+    }
+
+    /// Counts one use of a valid invitation code for the email and returns the invitation.
+    /// The check and the increment happen in one update, so the usage limit also holds for concurrent registrations.
+    pub async fn claim(code_hash: &str, email: &str) -> actix_web::Result<Option<Invitation>> {
+        Self::get_collection()
+            .await
+            .find_one_and_update(
+                doc! {
+                    "code_hash": code_hash,
+                    "$and": [
+                        { "$or": [{ "email": null }, { "email": email }] },
+                        { "$or": [{ "expiration_date": null }, { "expiration_date": {"$gt": DateTime::now()} }] },
+                        { "$or": [{ "max_uses": null }, { "$expr": {"$lt": ["$use_count", "$max_uses"]} }] },
+                    ]
+                },
+                doc! {
+                    "$inc": {
+                        "use_count": 1,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Gives back a claimed use, e.g. if the registration failed afterwards.
+    pub async fn release(invitation_id: ObjectId) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": invitation_id
+                },
+                doc! {
+                    "$inc": {
+                        "use_count": -1,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    pub async fn add_user(invitation_id: ObjectId, user_id: ObjectId) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": invitation_id
+                },
+                doc! {
+                    "$push": {
+                        "used_by": user_id,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+}
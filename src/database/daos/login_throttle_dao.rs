@@ -0,0 +1,250 @@
+use std::borrow::Borrow;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument};
+use mongodb::IndexModel;
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::login_throttle::{LoginThrottle, LoginThrottleKind};
+
+pub struct LoginThrottleDAO {}
+
+#[async_trait]
+impl DAO<LoginThrottle, ObjectId> for LoginThrottleDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<LoginThrottle>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<LoginThrottle>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(login_throttle: &mut LoginThrottle) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(login_throttle.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        login_throttle.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = login_throttle.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "login throttle insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(login_throttle: &LoginThrottle) -> actix_web::Result<u64> {
+        if let Some(id) = login_throttle.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "failed_attempts": login_throttle.failed_attempts,
+                            "locked_until": login_throttle.locked_until,
+                            "last_failure_date": login_throttle.last_failure_date,
+                            "expiration_date": login_throttle.expiration_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "login throttle id not found",
+        ))
+    }
+
+    async fn delete(login_throttle: &LoginThrottle) -> actix_web::Result<u64> {
+        if let Some(id) = login_throttle.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "login throttle id not found",
+        ))
+    }
+}
+
+// custom methods
+impl LoginThrottleDAO {
+    /// Lets MongoDB forget old failures and ensures there is only one entry per account or ip.
+    pub async fn create_indexes() -> actix_web::Result<()> {
+        let collection = Self::get_collection().await;
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {
+                        "expiration_date": 1
+                    })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {
+                        "kind": 1,
+                        "key": 1
+                    })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    pub async fn get_by_key(
+        kind: LoginThrottleKind,
+        key: &str,
+    ) -> actix_web::Result<Option<LoginThrottle>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "kind": kind.as_ref(),
+                    "key": key,
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Returns all accounts and ips, that are locked at the moment.
+    pub async fn get_all_locked() -> actix_web::Result<Vec<LoginThrottle>> {
+        let mut login_throttles: Vec<LoginThrottle> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "locked_until": {"$gt": DateTime::now()}
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(login_throttle) = cursor.next().await {
+            if let Ok(login_throttle) = login_throttle {
+                login_throttles.push(login_throttle);
+            }
+        }
+
+        Ok(login_throttles)
+    }
+
+    /// Counts a failed attempt and returns the updated entry, which is created for the first failure.
+    /// The increment happens in one update, so concurrent failures of several workers are all counted.
+    pub async fn register_failure(
+        kind: LoginThrottleKind,
+        key: &str,
+        ttl: time::Duration,
+    ) -> actix_web::Result<LoginThrottle> {
+        let now = DateTime::now();
+        Self::get_collection()
+            .await
+            .find_one_and_update(
+                doc! {
+                    "kind": kind.as_ref(),
+                    "key": key,
+                },
+                doc! {
+                    "$inc": {
+                        "failed_attempts": 1,
+                    },
+                    "$set": {
+                        "last_failure_date": now,
+                        "expiration_date": DateTime::from_millis(
+                            now.timestamp_millis() + ttl.whole_milliseconds() as i64,
+                        ),
+                    },
+                    "$setOnInsert": {
+                        "locked_until": null,
+                    }
+                },
+                FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(ReturnDocument::After)
+                    .build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .ok_or_else(|| {
+                actix_web::error::ErrorInternalServerError("login throttle upsert failed")
+            })
+    }
+
+    /// Forgets the failed attempts, e.g. after a successful login.
+    pub async fn delete_by_key(kind: LoginThrottleKind, key: &str) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_one(
+                doc! {
+                    "kind": kind.as_ref(),
+                    "key": key,
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+}
@@ -1,6 +1,14 @@
+pub mod api_token_dao;
 pub mod dao;
 pub mod directory_dao;
 pub mod file_dao;
+pub mod file_version_dao;
+pub mod invalidated_jwt_dao;
+pub mod invitation_dao;
+pub mod login_throttle_dao;
+pub mod oidc_login_state_dao;
+pub mod session_dao;
 pub mod share_dao;
 pub mod syncstate_dao;
 pub mod user_dao;
+pub mod verification_token_dao;
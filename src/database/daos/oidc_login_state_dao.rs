@@ -0,0 +1,149 @@
+use std::borrow::Borrow;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::IndexOptions;
+use mongodb::IndexModel;
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::oidc_login_state::OidcLoginState;
+
+pub struct OidcLoginStateDAO {}
+
+#[async_trait]
+impl DAO<OidcLoginState, ObjectId> for OidcLoginStateDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<OidcLoginState>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<OidcLoginState>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(oidc_login_state: &mut OidcLoginState) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(oidc_login_state.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        oidc_login_state.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = oidc_login_state.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "oidc login state insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(oidc_login_state: &OidcLoginState) -> actix_web::Result<u64> {
+        if let Some(id) = oidc_login_state.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "expiration_date": oidc_login_state.expiration_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "oidc login state id not found",
+        ))
+    }
+
+    async fn delete(oidc_login_state: &OidcLoginState) -> actix_web::Result<u64> {
+        if let Some(id) = oidc_login_state.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "oidc login state id not found",
+        ))
+    }
+}
+
+// custom methods
+impl OidcLoginStateDAO {
+    /// Lets MongoDB remove logins that have never been completed.
+    pub async fn create_expiration_index() -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {
+                        "expiration_date": 1
+                    })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Removes and returns the matching, not yet expired login, so a callback can only be processed once.
+    pub async fn take_valid(state: &str) -> actix_web::Result<Option<OidcLoginState>> {
+        Self::get_collection()
+            .await
+            .find_one_and_delete(
+                doc! {
+                    "state": state,
+                    "expiration_date": {"$gt": DateTime::now()},
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+}
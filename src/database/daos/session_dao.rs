@@ -0,0 +1,252 @@
+use std::borrow::Borrow;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::session::Session;
+
+pub struct SessionDAO {}
+
+#[async_trait]
+impl DAO<Session, ObjectId> for SessionDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<Session>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(oid: ObjectId, user_id: ObjectId) -> actix_web::Result<Option<Session>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(session: &mut Session) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(session.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        session.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = session.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "session insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(session: &Session) -> actix_web::Result<u64> {
+        if let Some(id) = session.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "refresh_token_hash": session.refresh_token_hash.to_owned(),
+                            "used_refresh_token_hashes": session.used_refresh_token_hashes.to_owned(),
+                            "revoked": session.revoked,
+                            "last_used_date": session.last_used_date,
+                            "expiration_date": session.expiration_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "session id not found",
+        ))
+    }
+
+    async fn delete(session: &Session) -> actix_web::Result<u64> {
+        if let Some(id) = session.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "session id not found",
+        ))
+    }
+}
+
+// custom methods
+impl SessionDAO {
+    /// Finds the session a refresh token belongs to, no matter if the token is the current or an already used one.
+    pub async fn get_by_refresh_token_hash(
+        refresh_token_hash: &str,
+    ) -> actix_web::Result<Option<Session>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "$or": [
+                        { "refresh_token_hash": refresh_token_hash },
+                        { "used_refresh_token_hashes": refresh_token_hash },
+                    ]
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Replaces the current refresh token of the session.
+    /// Returns false if the token has been rotated concurrently, so it must be treated as reused.
+    pub async fn rotate_refresh_token(
+        session: &mut Session,
+        new_refresh_token_hash: String,
+        new_expiration_date: DateTime,
+    ) -> actix_web::Result<bool> {
+        let now = DateTime::now();
+        let update_result = Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": session.id,
+                    "refresh_token_hash": session.refresh_token_hash.to_owned(),
+                    "revoked": false,
+                },
+                doc! {
+                    "$set": {
+                        "refresh_token_hash": new_refresh_token_hash.to_owned(),
+                        "last_used_date": now,
+                        "expiration_date": new_expiration_date,
+                    },
+                    "$push": {
+                        "used_refresh_token_hashes": session.refresh_token_hash.to_owned(),
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        if update_result.modified_count == 0 {
+            return Ok(false);
+        }
+
+        session.rotate_refresh_token(new_refresh_token_hash, new_expiration_date, now);
+        Ok(true)
+    }
+
+    /// Returns the sessions of the user, that are neither revoked nor expired.
+    pub async fn get_active_for_user(user_id: ObjectId) -> actix_web::Result<Vec<Session>> {
+        let mut sessions: Vec<Session> = Vec::new();
+
+        let mut cursor = Self::get_collection()
+            .await
+            .find(
+                doc! {
+                    "user_id": user_id,
+                    "revoked": false,
+                    "expiration_date": {"$gt": DateTime::now()},
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        while let Some(session) = cursor.next().await {
+            if let Ok(session) = session {
+                sessions.push(session);
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    /// Revokes the whole session, so neither the current nor any older refresh token can be used anymore.
+    pub async fn revoke(session_id: ObjectId) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": session_id
+                },
+                doc! {
+                    "$set": {
+                        "revoked": true,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Revokes all sessions of the user, e.g. after the password has been reset.
+    pub async fn revoke_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let update_result = Self::get_collection()
+            .await
+            .update_many(
+                doc! {
+                    "user_id": user_id,
+                    "revoked": false,
+                },
+                doc! {
+                    "$set": {
+                        "revoked": true,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(update_result.modified_count)
+    }
+
+    /// Removes all sessions of the user, e.g. when the account is deleted.
+    pub async fn delete_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+}
@@ -1,4 +1,5 @@
 use crate::database::daos::dao::DAO;
+use crate::database::daos::directory_dao::DirectoryDAO;
 use crate::database::daos::syncstate_dao::SyncStateDAO;
 use crate::database::entities::syncstate::{SyncState, SyncStateAction, SyncStateType};
 use crate::database::entities::user::User;
@@ -6,8 +7,10 @@ use crate::jwt_utils::extract_user_oid;
 use crate::Claims;
 use actix_jwt_authc::Authenticated;
 use async_trait::async_trait;
-use mongodb::bson::doc;
+use futures_util::StreamExt;
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOptions;
 use std::borrow::Borrow;
 use tracing::{event, Level};
 
@@ -74,6 +77,16 @@ impl DAO<User, ObjectId> for UserDAO {
                             "pw_hash": user.pw_hash.to_owned(),
                             "role": user.role.as_ref(),
                             "root_dir_id": user.root_dir_id.to_owned(),
+                            "totp_secret": user.totp_secret.to_owned(),
+                            "totp_enabled": user.totp_enabled,
+                            "totp_last_used_step": user.totp_last_used_step,
+                            "totp_recovery_code_hashes": user.totp_recovery_code_hashes.to_owned(),
+                            "email_verified": user.email_verified,
+                            "oidc_subject": user.oidc_subject.to_owned(),
+                            "ldap_dn": user.ldap_dn.to_owned(),
+                            "disabled": user.disabled,
+                            "deletion_date": user.deletion_date,
+                            "quota_bytes": user.quota_bytes,
                         }
                     },
                     None,
@@ -88,13 +101,168 @@ impl DAO<User, ObjectId> for UserDAO {
         ))
     }
 
-    async fn delete(_: &User) -> actix_web::Result<u64> {
-        todo!()
+    async fn delete(user: &User) -> actix_web::Result<u64> {
+        if let Some(id) = user.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            SyncStateDAO::delete_for_corresponding_id(id).await?;
+            let _ = SyncStateDAO::insert(&mut SyncState::new(
+                SyncStateType::User,
+                SyncStateAction::Delete,
+                id,
+                None,
+                id,
+            ))
+            .await?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "user id not found",
+        ))
     }
 }
 
 // custom methods
 impl UserDAO {
+    /// Adds the bytes to the used storage of the user, unless the quota would be exceeded.
+    /// The check and the increment happen in one update, so concurrent uploads cannot exceed the quota.
+    pub async fn reserve_used_bytes(
+        user_id: ObjectId,
+        bytes: i64,
+        quota_bytes: Option<i64>,
+    ) -> actix_web::Result<bool> {
+        let mut filter = doc! {
+            "_id": user_id
+        };
+        if let Some(quota_bytes) = quota_bytes {
+            if bytes > quota_bytes {
+                return Ok(false);
+            }
+            // users created before quotas existed have no counter yet
+            filter.insert(
+                "$or",
+                vec![
+                    doc! { "used_bytes": {"$lte": quota_bytes - bytes} },
+                    doc! { "used_bytes": null },
+                ],
+            );
+        }
+
+        let update_result = Self::get_collection()
+            .await
+            .update_one(
+                filter,
+                doc! {
+                    "$inc": {
+                        "used_bytes": bytes,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(update_result.modified_count == 1)
+    }
+
+    /// Removes the bytes of deleted files from the used storage of the user.
+    pub async fn release_used_bytes(user_id: ObjectId, bytes: i64) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": user_id
+                },
+                doc! {
+                    "$inc": {
+                        "used_bytes": -bytes,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Stores the time step of a used totp code, fails if the step or a later one has been used
+    /// in the meantime, e.g. by a parallel login with the same code.
+    pub async fn use_totp_step(user_id: ObjectId, step: i64) -> actix_web::Result<bool> {
+        let update_result = Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": user_id,
+                    "$or": [
+                        {"totp_last_used_step": null},
+                        {"totp_last_used_step": {"$lt": step}}
+                    ]
+                },
+                doc! {
+                    "$set": {
+                        "totp_last_used_step": step,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(update_result.modified_count == 1)
+    }
+
+    /// Removes a used recovery code, fails if it has been removed in the meantime.
+    pub async fn use_totp_recovery_code(
+        user_id: ObjectId,
+        code_hash: &str,
+    ) -> actix_web::Result<bool> {
+        let update_result = Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": user_id,
+                    "totp_recovery_code_hashes": code_hash
+                },
+                doc! {
+                    "$pull": {
+                        "totp_recovery_code_hashes": code_hash,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(update_result.modified_count == 1)
+    }
+
+    pub async fn set_used_bytes(user_id: ObjectId, used_bytes: i64) -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .update_one(
+                doc! {
+                    "_id": user_id
+                },
+                doc! {
+                    "$set": {
+                        "used_bytes": used_bytes,
+                    }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
     pub async fn get_authenticated(
         authenticated: &Authenticated<Claims>,
     ) -> actix_web::Result<Option<User>> {
@@ -119,9 +287,168 @@ impl UserDAO {
             .map_err(|e| actix_web::error::ErrorInternalServerError(e))
     }
 
+    pub async fn get_by_oidc_subject(oidc_subject: &str) -> actix_web::Result<Option<User>> {
+        UserDAO::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "oidc_subject": oidc_subject
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    pub async fn get_by_ldap_dn(ldap_dn: &str) -> actix_web::Result<Option<User>> {
+        UserDAO::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "ldap_dn": ldap_dn
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Inserts a new user together with the root directory of the user.
+    /// If the root directory cannot be created, the user is removed again, because a user without
+    /// root directory cannot log in and would block the email for another registration.
+    pub async fn insert_with_root_dir(user: &mut User) -> actix_web::Result<ObjectId> {
+        let inserted_user_id = UserDAO::insert(user).await?;
+
+        let root_dir_result = match DirectoryDAO::create_user_root_dir(inserted_user_id).await {
+            Ok(root_dir_id) => {
+                user.root_dir_id = Some(root_dir_id);
+                UserDAO::update(user).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = root_dir_result {
+            if let Err(cleanup_error) = Self::delete_incomplete(user).await {
+                event!(
+                    Level::ERROR,
+                    "Failed to remove user {} without root directory: {}",
+                    inserted_user_id,
+                    cleanup_error
+                );
+            }
+            return Err(e);
+        }
+
+        Ok(inserted_user_id)
+    }
+
+    async fn delete_incomplete(user: &User) -> actix_web::Result<()> {
+        if let Some(id) = user.id {
+            DirectoryDAO::delete_all_for_user(id).await?;
+        }
+        UserDAO::delete(user).await?;
+        Ok(())
+    }
+
+    /// Returns the users whose deletion grace period ended before the given date.
+    pub async fn get_due_for_deletion(before: DateTime) -> actix_web::Result<Vec<User>> {
+        let mut cursor = UserDAO::get_collection()
+            .await
+            .find(
+                doc! {
+                    "deletion_date": {"$lte": before}
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let mut users: Vec<User> = Vec::new();
+        while let Some(user) = cursor.next().await {
+            if let Ok(user) = user {
+                users.push(user);
+            }
+        }
+
+        Ok(users)
+    }
+
+    /// Returns a page of users, optionally filtered by a part of their name or email, and the total count.
+    pub async fn search(
+        search: Option<&str>,
+        page: u64,
+        page_size: u64,
+    ) -> actix_web::Result<(Vec<User>, u64)> {
+        let filter = match search {
+            Some(search) if !search.is_empty() => {
+                let pattern = escape_regex(search);
+                doc! {
+                    "$or": [
+                        { "firstname": { "$regex": pattern.as_str(), "$options": "i" } },
+                        { "lastname": { "$regex": pattern.as_str(), "$options": "i" } },
+                        { "email": { "$regex": pattern.as_str(), "$options": "i" } },
+                    ]
+                }
+            }
+            _ => doc! {},
+        };
+
+        let total = UserDAO::get_collection()
+            .await
+            .count_documents(filter.clone(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let mut cursor = UserDAO::get_collection()
+            .await
+            .find(
+                filter,
+                FindOptions::builder()
+                    .sort(doc! { "email": 1 })
+                    // pages after the last one are empty, without overflowing for large page numbers
+                    .skip(page.saturating_mul(page_size).min(total))
+                    .limit(page_size as i64)
+                    .build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let mut users: Vec<User> = Vec::new();
+        while let Some(user) = cursor.next().await {
+            if let Ok(user) = user {
+                users.push(user);
+            }
+        }
+
+        Ok((users, total))
+    }
+
     pub async fn exists(email: &String) -> actix_web::Result<bool> {
         Ok(UserDAO::get_by_email(email.to_owned().as_str())
             .await?
             .is_some())
     }
 }
+
+/// Escapes user input for the use in a MongoDB regular expression.
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.^$|?*+()[]{}-/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_regex() {
+        assert_eq!(escape_regex("ada"), "ada");
+        assert_eq!(escape_regex("a.b+c@d"), "a\\.b\\+c@d");
+        assert_eq!(escape_regex(".*(x)"), "\\.\\*\\(x\\)");
+    }
+}
@@ -0,0 +1,189 @@
+use std::borrow::Borrow;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::IndexOptions;
+use mongodb::IndexModel;
+
+use crate::database::daos::dao::DAO;
+use crate::database::entities::verification_token::{VerificationPurpose, VerificationToken};
+
+pub struct VerificationTokenDAO {}
+
+#[async_trait]
+impl DAO<VerificationToken, ObjectId> for VerificationTokenDAO {
+    async fn get(oid: ObjectId) -> actix_web::Result<Option<VerificationToken>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn get_with_user(
+        oid: ObjectId,
+        user_id: ObjectId,
+    ) -> actix_web::Result<Option<VerificationToken>> {
+        Self::get_collection()
+            .await
+            .find_one(
+                doc! {
+                    "_id": oid,
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    async fn insert(verification_token: &mut VerificationToken) -> actix_web::Result<ObjectId> {
+        let insert_result = Self::get_collection()
+            .await
+            .insert_one(verification_token.borrow(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        verification_token.id = insert_result.inserted_id.as_object_id();
+        if let Some(id) = verification_token.id {
+            return Ok(id);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "verification token insert failed converting inserted_id to ObjectId",
+        ))
+    }
+
+    async fn update(verification_token: &VerificationToken) -> actix_web::Result<u64> {
+        if let Some(id) = verification_token.id {
+            let update_result = Self::get_collection()
+                .await
+                .update_one(
+                    doc! {
+                        "_id": id
+                    },
+                    doc! {
+                        "$set": {
+                            "expiration_date": verification_token.expiration_date,
+                        }
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(update_result.modified_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "verification token id not found",
+        ))
+    }
+
+    async fn delete(verification_token: &VerificationToken) -> actix_web::Result<u64> {
+        if let Some(id) = verification_token.id {
+            let delete_result = Self::get_collection()
+                .await
+                .delete_one(
+                    doc! {
+                        "_id": id
+                    },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+
+            return Ok(delete_result.deleted_count);
+        }
+
+        Err(actix_web::error::ErrorInternalServerError(
+            "verification token id not found",
+        ))
+    }
+}
+
+// custom methods
+impl VerificationTokenDAO {
+    /// Lets MongoDB remove tokens that have never been used.
+    pub async fn create_expiration_index() -> actix_web::Result<()> {
+        Self::get_collection()
+            .await
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {
+                        "expiration_date": 1
+                    })
+                    .options(
+                        IndexOptions::builder()
+                            .expire_after(Duration::from_secs(0))
+                            .build(),
+                    )
+                    .build(),
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
+
+    /// Removes and returns the matching, not yet expired token, so it can only be used once.
+    pub async fn take_valid(
+        token_hash: &str,
+        purpose: VerificationPurpose,
+    ) -> actix_web::Result<Option<VerificationToken>> {
+        Self::get_collection()
+            .await
+            .find_one_and_delete(
+                doc! {
+                    "token_hash": token_hash,
+                    "purpose": purpose.as_ref(),
+                    "expiration_date": {"$gt": DateTime::now()},
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)
+    }
+
+    /// Removes all tokens of the user for the given purpose, e.g. before a new one is sent.
+    pub async fn delete_for_user_and_purpose(
+        user_id: ObjectId,
+        purpose: VerificationPurpose,
+    ) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id,
+                    "purpose": purpose.as_ref(),
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+
+    /// Removes all verification tokens of the user, e.g. when the account is deleted.
+    pub async fn delete_all_for_user(user_id: ObjectId) -> actix_web::Result<u64> {
+        let delete_result = Self::get_collection()
+            .await
+            .delete_many(
+                doc! {
+                    "user_id": user_id
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(delete_result.deleted_count)
+    }
+}
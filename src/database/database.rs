@@ -6,16 +6,24 @@ pub trait MyDBModel {
     fn type_name() -> &'static str;
 }
 
+pub async fn establish_client() -> Option<Client> {
+    let settings = SETTINGS.get().unwrap();
+    Client::with_uri_str(&settings.database.url).await.ok()
+}
+
 pub async fn establish_connection() -> Option<Database> {
     let settings = SETTINGS.get().unwrap();
-    let client = Client::with_uri_str(&settings.database.url).await;
-    if client.is_err() {
-        return None;
-    }
-    Some(client.unwrap().database(&settings.database.name))
+    Some(establish_client().await?.database(&settings.database.name))
 }
 
 pub async fn get_collection<ENTITY: MyDBModel>() -> Collection<ENTITY> {
     let db = establish_connection().await.unwrap();
     db.collection::<ENTITY>(ENTITY::type_name())
 }
+
+/// Returns the collection of the given client, e.g. to use it with a session of that client.
+pub fn get_collection_of_client<ENTITY: MyDBModel>(client: &Client) -> Collection<ENTITY> {
+    client
+        .database(&SETTINGS.get().unwrap().database.name)
+        .collection::<ENTITY>(ENTITY::type_name())
+}
@@ -0,0 +1,197 @@
+use crate::database::database::MyDBModel;
+use actix_web::http::Method;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+pub const API_TOKEN_PREFIX: &str = "thunder_pat_";
+
+/// A named personal access token, e.g. for scripts and CI. Only the hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub expiration_date: Option<DateTime>,
+    pub last_used_date: Option<DateTime>,
+    pub creation_date: DateTime,
+}
+
+impl MyDBModel for ApiToken {
+    fn type_name() -> &'static str {
+        "ApiToken"
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenScope {
+    Read,   // list directories, download files and directories, syncstates
+    Upload, // upload files and create directories
+    Share,  // create, list and delete shares
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenCreate {
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub valid_until: Option<i64>, // timestamp with milliseconds
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenDelete {
+    pub id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenCreateResponse {
+    pub id: ObjectId,
+    pub token: String, // the plain token is only returned once
+}
+
+#[derive(Serialize)]
+pub struct ApiTokenInfo {
+    pub id: ObjectId,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub expiration_date_ts: Option<i64>,
+    pub last_used_date_ts: Option<i64>,
+    pub creation_date_ts: i64,
+}
+
+impl ApiTokenScope {
+    /// Returns the scope a token needs for the requested route.
+    /// Routes without a scope (e.g. token or user management) cannot be used with api tokens at all.
+    pub fn required_for(method: &Method, path: &str) -> Option<ApiTokenScope> {
+        match (method.as_str(), path.trim_end_matches('/')) {
+            ("GET", "/v1/data/directory")
+            | ("GET", "/v1/data/download/file")
+            | ("GET", "/v1/data/download/file/version")
+            | ("GET", "/v1/data/file/versions")
+            | ("GET", "/v1/data/download/directory")
+            | ("GET", "/v1/data/directory/ancestors")
+            | ("GET", "/v1/data/path")
+            | ("GET", "/v1/data/download/path")
+            | ("GET", "/v1/user/syncstate")
+            | ("GET", "/v1/share")
+            | ("GET", "/v1/share/download") => Some(ApiTokenScope::Read),
+            ("GET", path)
+                if path.starts_with("/v1/data/path/")
+                    || path.starts_with("/v1/data/download/path/") =>
+            {
+                Some(ApiTokenScope::Read)
+            }
+            ("PUT", "/v1/data/file")
+            | ("POST", "/v1/data/directory")
+            | ("POST", "/v1/data/file/copy")
+            | ("POST", "/v1/data/directory/copy")
+            | ("PUT", "/v1/data/path") => Some(ApiTokenScope::Upload),
+            ("POST", "/v1/data/tus") => Some(ApiTokenScope::Upload),
+            ("PUT", path) if path.starts_with("/v1/data/path/") => Some(ApiTokenScope::Upload),
+            ("HEAD", path) | ("PATCH", path) | ("DELETE", path)
+                if path.starts_with("/v1/data/tus/") =>
+            {
+                Some(ApiTokenScope::Upload)
+            }
+            ("GET", "/v1/user/shares")
+            | ("DELETE", "/v1/share")
+            | ("GET", "/v1/share/directory")
+            | ("POST", "/v1/share/directory")
+            | ("GET", "/v1/share/file")
+            | ("POST", "/v1/share/file") => Some(ApiTokenScope::Share),
+            _ => None,
+        }
+    }
+}
+
+impl ApiToken {
+    pub fn new(
+        user_id: ObjectId,
+        name: String,
+        token_hash: String,
+        scopes: Vec<ApiTokenScope>,
+        valid_until: Option<i64>,
+    ) -> ApiToken {
+        ApiToken {
+            id: None,
+            user_id,
+            name,
+            token_hash,
+            scopes,
+            expiration_date: valid_until.map(DateTime::from_millis),
+            last_used_date: None,
+            creation_date: DateTime::now(),
+        }
+    }
+    pub fn is_expired(&self) -> bool {
+        match self.expiration_date {
+            Some(expiration_date) => expiration_date < DateTime::now(),
+            None => false,
+        }
+    }
+    pub fn has_scope(&self, scope: ApiTokenScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+    pub fn get_info(&self) -> ApiTokenInfo {
+        ApiTokenInfo {
+            id: self.id.unwrap(),
+            name: self.name.clone(),
+            scopes: self.scopes.clone(),
+            expiration_date_ts: self.expiration_date.map(|date| date.timestamp_millis()),
+            last_used_date_ts: self.last_used_date.map(|date| date.timestamp_millis()),
+            creation_date_ts: self.creation_date.timestamp_millis(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::PUT, "/v1/data/file"),
+            Some(ApiTokenScope::Upload)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::GET, "/v1/data/directory"),
+            Some(ApiTokenScope::Read)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::DELETE, "/v1/share/"),
+            Some(ApiTokenScope::Share)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::DELETE, "/v1/data/file"),
+            None
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::PATCH, "/v1/data/tus/some-uuid"),
+            Some(ApiTokenScope::Upload)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::POST, "/v1/data/directory/copy"),
+            Some(ApiTokenScope::Upload)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::GET, "/v1/data/path/projects/report.pdf"),
+            Some(ApiTokenScope::Read)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::PUT, "/v1/data/path/projects/2024"),
+            Some(ApiTokenScope::Upload)
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::DELETE, "/v1/data/path/projects"),
+            None
+        );
+        assert_eq!(
+            ApiTokenScope::required_for(&Method::POST, "/v1/user/tokens"),
+            None
+        );
+    }
+}
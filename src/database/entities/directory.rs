@@ -15,7 +15,11 @@ pub struct Directory {
     pub parent_id: Option<ObjectId>,
     pub name: String,
     pub creation_date: DateTime,
-    pub child_ids: Vec<ObjectId>,
+    pub child_ids: Vec<ObjectId>, // includes the children in the trash
+    #[serde(default)]
+    pub deletion_date: Option<DateTime>, // set while the directory is in the trash
+    #[serde(default)]
+    pub trashed_with: Option<ObjectId>, // the ancestor, whose deletion moved the directory to the trash
 }
 
 impl MyDBModel for Directory {
@@ -39,11 +43,49 @@ pub struct DirectoryPatch {
     pub parent_id: Option<String>, // null or the new parent directory document id
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryCopy {
+    pub id: String,
+    pub target_directory: Option<String>, // the root directory if not set
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DirectoryGet {
     pub id: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryAncestorsGet {
+    pub id: String,
+}
+
+/// One entry of the breadcrumb from the root directory to a directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryAncestor {
+    pub id: ObjectId,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct PathDownloadQueryParams {
+    pub archive: Option<String>,
+}
+
+/// The directory or file, that was addressed by a path.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PathGetResponse {
+    Directory {
+        id: ObjectId,
+        name: String,
+        parent_id: Option<ObjectId>,
+        creation_date_ts: i64,
+        dirs: Vec<DirectoryGetResponseObject>,
+        files: Vec<File>,
+    },
+    File(File),
+}
+
 #[derive(Deserialize)]
 pub struct GetDirectoryArchiveQueryParams {
     pub id: Option<String>,
@@ -55,6 +97,21 @@ pub struct DirectoryDelete {
     pub id: String,
 }
 
+#[derive(Serialize)]
+pub struct TrashGetResponse {
+    pub dirs: Vec<DirectoryTrashInfo>,
+    pub files: Vec<File>,
+}
+
+#[derive(Serialize)]
+pub struct DirectoryTrashInfo {
+    pub id: ObjectId,
+    pub name: String,
+    pub parent_id: Option<ObjectId>, // the directory it will be restored to
+    pub creation_date_ts: i64,
+    pub deletion_date_ts: i64,
+}
+
 #[derive(Serialize)]
 pub struct DirectoryGetResponse {
     pub dirs: Vec<DirectoryGetResponseObject>,
@@ -71,14 +128,41 @@ pub struct DirectoryGetResponseObject {
 }
 
 impl Directory {
+    pub fn get_trash_info(&self) -> Option<DirectoryTrashInfo> {
+        Some(DirectoryTrashInfo {
+            id: self.id?,
+            name: self.name.to_owned(),
+            parent_id: self.parent_id,
+            creation_date_ts: self.creation_date.timestamp_millis(),
+            deletion_date_ts: self.deletion_date?.timestamp_millis(),
+        })
+    }
+    /// Returns the files of the directory, that are not in the trash.
     pub async fn get_files(&self) -> Vec<File> {
         if let Some(id) = self.id {
             return FileDAO::get_files_by_parent_id(id)
                 .await
-                .unwrap_or(Vec::new());
+                .unwrap_or(Vec::new())
+                .into_iter()
+                .filter(|file| file.deletion_date.is_none())
+                .collect();
         }
         Vec::new()
     }
+    /// Returns the files without unfinished uploads, as shown to clients.
+    pub async fn get_finished_files(&self) -> Vec<File> {
+        self.get_files()
+            .await
+            .into_iter()
+            .filter(|file| file.finished)
+            .collect()
+    }
+    pub async fn get_file_with_name(&self, name: &str) -> Option<File> {
+        self.get_files()
+            .await
+            .into_iter()
+            .find(|file| file.name == name)
+    }
     pub async fn has_file_with_name(&self, name: &String) -> bool {
         for file in self.get_files().await {
             if file.name.eq(name) {
@@ -87,4 +171,21 @@ impl Directory {
         }
         false
     }
+    /// Returns the first of "name", "name (1).ext", ..., that is not used in the directory yet.
+    pub async fn get_free_file_name(&self, name: &str) -> String {
+        let names: Vec<String> = self
+            .get_files()
+            .await
+            .into_iter()
+            .map(|file| file.name)
+            .collect();
+
+        let mut candidate = name.to_string();
+        let mut counter = 1;
+        while names.contains(&candidate) {
+            candidate = File::get_renamed_name(name, counter);
+            counter += 1;
+        }
+        candidate
+    }
 }
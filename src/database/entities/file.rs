@@ -10,11 +10,27 @@ pub struct File {
     pub parent_id: ObjectId,
     pub user_id: ObjectId,
     pub uuid: String,
-    pub hash: String,
+    pub hash: String, // hex encoded sha256 of the content
+    #[serde(default)]
+    pub size: i64, // in bytes, the received bytes (upload offset) while the upload is not finished
     pub mime: String,
     pub name: String,
-    pub finished: bool,
+    pub finished: bool, // unfinished resumable uploads are hidden from listings and downloads
+    #[serde(default)]
+    pub upload_length: Option<i64>, // announced size of a resumable upload
+    #[serde(default)]
+    pub upload_expiration_date: Option<DateTime>, // unfinished uploads are removed afterwards
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_lock: Option<String>, // set while a request appends to the upload, see `tus::UploadLock`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_locked_until: Option<DateTime>, // the lock expires, if its holder stops renewing it
     pub creation_date: DateTime,
+    #[serde(default)]
+    pub modification_date: Option<DateTime>, // supplied by the client, e.g. the mtime of the local copy
+    #[serde(default)]
+    pub deletion_date: Option<DateTime>, // set while the file is in the trash
+    #[serde(default)]
+    pub trashed_with: Option<ObjectId>, // the directory, whose deletion moved the file to the trash
 }
 
 impl MyDBModel for File {
@@ -23,15 +39,79 @@ impl MyDBModel for File {
     }
 }
 
+impl File {
+    /// Returns the name with a counter in front of the extension, e.g. "report (2).pdf".
+    pub fn get_renamed_name(name: &str, counter: u32) -> String {
+        match name.rfind('.') {
+            // a leading dot belongs to the name of hidden files, it is no extension
+            Some(index) if index > 0 => {
+                format!("{} ({}){}", &name[..index], counter, &name[index..])
+            }
+            _ => format!("{} ({})", name, counter),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 pub struct GetSingleQueryParams {
     pub uuid: String,
     pub archive: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct FileCopy {
+    pub uuid: String,
+    pub target_directory: Option<String>, // the root directory if not set
+}
+
+#[derive(Deserialize)]
+pub struct FileUuid {
+    pub uuid: String,
+}
+
 #[derive(Deserialize)]
 pub struct MultiUploadQueryParams {
     pub directory: String,
+    #[serde(default)]
+    pub conflict: UploadConflictStrategy,
+}
+
+#[derive(Deserialize)]
+pub struct PathUploadQueryParams {
+    #[serde(default)]
+    pub create_parents: bool, // create the missing directories of the path
+    #[serde(default)]
+    pub conflict: UploadConflictStrategy,
+}
+
+/// What happens to an uploaded file, if the directory already contains a file with the same name.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadConflictStrategy {
+    #[default]
+    Skip, // keep the existing file and discard the upload
+    Rename,    // store the upload as "name (1).ext"
+    Overwrite, // replace the content of the existing file
+    Version,   // replace the content, but keep the previous content as a version
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadOutcome {
+    Created,
+    Skipped,
+    Renamed,
+    Overwritten,
+    Versioned,
+    Rejected, // not stored, because it exceeds the storage quota
+}
+
+/// Reports what happened to one file of a multi upload.
+#[derive(Debug, Serialize)]
+pub struct UploadResult {
+    pub name: String, // the (sanitized) name the file has been uploaded with
+    pub outcome: UploadOutcome,
+    pub file: Option<File>, // not set, if the upload has been skipped or rejected
 }
 
 #[derive(Deserialize)]
@@ -40,3 +120,19 @@ pub struct FilePatch {
     pub new_name: Option<String>,
     pub new_directory: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_renamed_name() {
+        assert_eq!(File::get_renamed_name("report.pdf", 1), "report (1).pdf");
+        assert_eq!(
+            File::get_renamed_name("archive.tar.gz", 2),
+            "archive.tar (2).gz"
+        );
+        assert_eq!(File::get_renamed_name("README", 3), "README (3)");
+        assert_eq!(File::get_renamed_name(".bashrc", 1), ".bashrc (1)");
+    }
+}
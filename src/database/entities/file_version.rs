@@ -0,0 +1,62 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// A previous content of a file, kept when the file got replaced by a versioning upload.
+/// The blob still belongs to the owner and counts toward the quota.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileVersion {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub file_id: ObjectId,
+    pub user_id: ObjectId,
+    pub uuid: String, // uuid of the kept blob
+    pub hash: String,
+    pub size: i64,
+    pub mime: String,
+    pub creation_date: DateTime, // when the content has been stored
+    pub modification_date: Option<DateTime>,
+    pub replaced_date: DateTime,
+}
+
+impl MyDBModel for FileVersion {
+    fn type_name() -> &'static str {
+        "FileVersion"
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FileVersionsQueryParams {
+    pub uuid: String, // uuid of the file
+}
+
+#[derive(Deserialize)]
+pub struct FileVersionId {
+    pub id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct FileVersionInfo {
+    pub id: ObjectId,
+    pub hash: String,
+    pub size: i64,
+    pub mime: String,
+    pub creation_date_ts: i64,
+    pub modification_date_ts: Option<i64>,
+    pub replaced_date_ts: i64,
+}
+
+impl FileVersion {
+    pub fn get_info(&self) -> Option<FileVersionInfo> {
+        Some(FileVersionInfo {
+            id: self.id?,
+            hash: self.hash.to_owned(),
+            size: self.size,
+            mime: self.mime.to_owned(),
+            creation_date_ts: self.creation_date.timestamp_millis(),
+            modification_date_ts: self.modification_date.map(|date| date.timestamp_millis()),
+            replaced_date_ts: self.replaced_date.timestamp_millis(),
+        })
+    }
+}
@@ -0,0 +1,75 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// A revoked access token, kept until the token would have expired anyway (TTL index).
+/// If `issued_before` is set, all access tokens of the user issued before are revoked and `jwt` is empty.
+/// If `session_id` is set, all access tokens of that session are revoked and `jwt` is empty.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvalidatedJWT {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub jwt: String,
+    #[serde(default)]
+    pub issued_before: Option<DateTime>,
+    #[serde(default)]
+    pub session_id: Option<ObjectId>,
+    pub expiration_date: DateTime,
+    pub creation_date: DateTime,
+}
+
+impl MyDBModel for InvalidatedJWT {
+    fn type_name() -> &'static str {
+        "InvalidatedJWT"
+    }
+}
+
+impl InvalidatedJWT {
+    pub fn new(user_id: ObjectId, jwt: String, exp: usize) -> InvalidatedJWT {
+        InvalidatedJWT {
+            id: None,
+            user_id,
+            jwt,
+            issued_before: None,
+            session_id: None,
+            expiration_date: DateTime::from_millis(exp as i64 * 1000),
+            creation_date: DateTime::now(),
+        }
+    }
+    pub fn new_for_all_of_user(user_id: ObjectId, jwt_ttl: time::Duration) -> InvalidatedJWT {
+        let now = DateTime::now();
+        InvalidatedJWT {
+            id: None,
+            user_id,
+            jwt: String::new(),
+            issued_before: Some(now),
+            session_id: None,
+            // all tokens issued before are expired by then
+            expiration_date: DateTime::from_millis(
+                now.timestamp_millis() + jwt_ttl.whole_milliseconds() as i64,
+            ),
+            creation_date: now,
+        }
+    }
+    pub fn new_for_session(
+        user_id: ObjectId,
+        session_id: ObjectId,
+        jwt_ttl: time::Duration,
+    ) -> InvalidatedJWT {
+        let now = DateTime::now();
+        InvalidatedJWT {
+            id: None,
+            user_id,
+            jwt: String::new(),
+            issued_before: None,
+            session_id: Some(session_id),
+            // the session cannot issue new tokens, the existing ones are expired by then
+            expiration_date: DateTime::from_millis(
+                now.timestamp_millis() + jwt_ttl.whole_milliseconds() as i64,
+            ),
+            creation_date: now,
+        }
+    }
+}
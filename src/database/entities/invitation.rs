@@ -0,0 +1,123 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// An invitation code issued by an administrator, that allows registering while public registration is disabled.
+/// Only the hash of the code is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invitation {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub code_hash: String,
+    pub email: Option<String>, // if set, only this email can register with the code
+    pub max_uses: Option<u32>, // unlimited if not set
+    pub use_count: u32,
+    pub used_by: Vec<ObjectId>,
+    pub created_by: Option<ObjectId>, // not set if created via command line
+    pub expiration_date: Option<DateTime>,
+    pub creation_date: DateTime,
+}
+
+impl MyDBModel for Invitation {
+    fn type_name() -> &'static str {
+        "Invitation"
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvitationCreate {
+    pub email: Option<String>,
+    pub max_uses: Option<u32>,
+    pub valid_until: Option<i64>, // timestamp with milliseconds
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvitationDelete {
+    pub id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct InvitationCreateResponse {
+    pub id: ObjectId,
+    pub code: String, // the plain code is only returned once
+}
+
+#[derive(Serialize)]
+pub struct InvitationInfo {
+    pub id: ObjectId,
+    pub email: Option<String>,
+    pub max_uses: Option<u32>,
+    pub use_count: u32,
+    pub used_by: Vec<ObjectId>,
+    pub usable: bool,
+    pub created_by: Option<ObjectId>,
+    pub expiration_date_ts: Option<i64>,
+    pub creation_date_ts: i64,
+}
+
+impl Invitation {
+    pub fn new(
+        code_hash: String,
+        email: Option<String>,
+        max_uses: Option<u32>,
+        valid_until: Option<i64>,
+        created_by: Option<ObjectId>,
+    ) -> Invitation {
+        Invitation {
+            id: None,
+            code_hash,
+            email,
+            max_uses,
+            use_count: 0,
+            used_by: vec![],
+            created_by,
+            expiration_date: valid_until.map(DateTime::from_millis),
+            creation_date: DateTime::now(),
+        }
+    }
+    pub fn is_usable(&self) -> bool {
+        let expired = match self.expiration_date {
+            Some(expiration_date) => expiration_date < DateTime::now(),
+            None => false,
+        };
+        let exhausted = match self.max_uses {
+            Some(max_uses) => self.use_count >= max_uses,
+            None => false,
+        };
+        !expired && !exhausted
+    }
+    pub fn get_info(&self) -> InvitationInfo {
+        InvitationInfo {
+            id: self.id.unwrap(),
+            email: self.email.clone(),
+            max_uses: self.max_uses,
+            use_count: self.use_count,
+            used_by: self.used_by.clone(),
+            usable: self.is_usable(),
+            created_by: self.created_by,
+            expiration_date_ts: self.expiration_date.map(|date| date.timestamp_millis()),
+            creation_date_ts: self.creation_date.timestamp_millis(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_usable() {
+        let mut invitation = Invitation::new("hash".to_string(), None, Some(2), None, None);
+        assert!(invitation.is_usable());
+
+        invitation.use_count = 2;
+        assert!(!invitation.is_usable());
+
+        invitation.max_uses = None;
+        assert!(invitation.is_usable());
+
+        invitation.expiration_date = Some(DateTime::from_millis(0));
+        assert!(!invitation.is_usable());
+    }
+}
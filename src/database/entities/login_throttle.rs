@@ -0,0 +1,106 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use strum_macros::AsRefStr;
+
+/// Failed login or registration attempts of an account or a client ip.
+/// Once too many attempts failed, the key is locked for an exponentially growing time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginThrottle {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub kind: LoginThrottleKind,
+    pub key: String, // the lowercase email or the client ip
+    pub failed_attempts: u32,
+    pub locked_until: Option<DateTime>,
+    pub last_failure_date: DateTime,
+    pub expiration_date: DateTime,
+}
+
+impl MyDBModel for LoginThrottle {
+    fn type_name() -> &'static str {
+        "LoginThrottle"
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LoginThrottleKind {
+    Account,
+    Ip,
+}
+
+#[derive(Serialize)]
+pub struct LoginThrottleInfo {
+    pub id: ObjectId,
+    pub kind: LoginThrottleKind,
+    pub key: String,
+    pub failed_attempts: u32,
+    pub locked_until_ts: Option<i64>,
+    pub last_failure_date_ts: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginThrottleDelete {
+    pub id: ObjectId,
+}
+
+impl LoginThrottle {
+    /// Returns how long the key has to be locked after the given number of failed attempts.
+    /// The lockout starts at `max_failed_attempts` and doubles with every further failure.
+    pub fn get_lockout_duration(
+        failed_attempts: u32,
+        max_failed_attempts: u32,
+        base_lockout_seconds: i64,
+        max_lockout_seconds: i64,
+    ) -> Option<time::Duration> {
+        if failed_attempts < max_failed_attempts {
+            return None;
+        }
+
+        let factor = 1_i64
+            .checked_shl(failed_attempts - max_failed_attempts)
+            .unwrap_or(i64::MAX);
+        Some(time::Duration::seconds(
+            base_lockout_seconds
+                .saturating_mul(factor)
+                .min(max_lockout_seconds),
+        ))
+    }
+    pub fn get_info(&self) -> Option<LoginThrottleInfo> {
+        Some(LoginThrottleInfo {
+            id: self.id?,
+            kind: self.kind,
+            key: self.key.to_owned(),
+            failed_attempts: self.failed_attempts,
+            locked_until_ts: self
+                .locked_until
+                .map(|locked_until| locked_until.timestamp_millis()),
+            last_failure_date_ts: self.last_failure_date.timestamp_millis(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_lockout_duration() {
+        assert_eq!(LoginThrottle::get_lockout_duration(4, 5, 30, 3600), None);
+        assert_eq!(
+            LoginThrottle::get_lockout_duration(5, 5, 30, 3600),
+            Some(time::Duration::seconds(30))
+        );
+        assert_eq!(
+            LoginThrottle::get_lockout_duration(7, 5, 30, 3600),
+            Some(time::Duration::seconds(120))
+        );
+        assert_eq!(
+            LoginThrottle::get_lockout_duration(100, 5, 30, 3600),
+            Some(time::Duration::seconds(3600))
+        );
+    }
+}
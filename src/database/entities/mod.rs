@@ -1,5 +1,13 @@
+pub mod api_token;
 pub mod directory;
 pub mod file;
+pub mod file_version;
+pub mod invalidated_jwt;
+pub mod invitation;
+pub mod login_throttle;
+pub mod oidc_login_state;
+pub mod session;
 pub mod share;
 pub mod syncstate;
 pub mod user;
+pub mod verification_token;
@@ -0,0 +1,52 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+const OIDC_LOGIN_STATE_TTL_MINUTES: i64 = 10;
+
+/// A started OpenID Connect login, identified by the `state` parameter that the provider sends back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcLoginState {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expiration_date: DateTime,
+    pub creation_date: DateTime,
+}
+
+impl MyDBModel for OidcLoginState {
+    fn type_name() -> &'static str {
+        "OidcLoginState"
+    }
+}
+
+#[derive(Serialize)]
+pub struct OidcLoginResponse {
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OidcCallback {
+    pub code: String,
+    pub state: String,
+}
+
+impl OidcLoginState {
+    pub fn new(state: String, nonce: String, code_verifier: String) -> OidcLoginState {
+        OidcLoginState {
+            id: None,
+            state,
+            nonce,
+            code_verifier,
+            expiration_date: DateTime::from_millis(
+                DateTime::now().timestamp_millis()
+                    + time::Duration::minutes(OIDC_LOGIN_STATE_TTL_MINUTES).whole_milliseconds()
+                        as i64,
+            ),
+            creation_date: DateTime::now(),
+        }
+    }
+}
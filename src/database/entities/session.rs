@@ -0,0 +1,179 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+/// A login session (token family) of a user.
+/// Every refresh rotates `refresh_token_hash`, the replaced hashes are kept to detect reuse.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub refresh_token_hash: String,
+    pub used_refresh_token_hashes: Vec<String>,
+    pub revoked: bool,
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub ip: Option<String>, // of the login
+    pub creation_date: DateTime,
+    pub last_used_date: DateTime,
+    pub expiration_date: DateTime,
+}
+
+impl MyDBModel for Session {
+    fn type_name() -> &'static str {
+        "Session"
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRefresh {
+    pub refresh_token: String,
+}
+
+/// How a presented refresh token relates to the session it has been found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshTokenCheck {
+    Current,  // the latest token, it may be rotated
+    Reused,   // an already rotated token, it may have been stolen
+    Inactive, // the session has expired or has been revoked
+}
+
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub id: ObjectId,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub current: bool, // the session of the requesting access token
+    pub creation_date_ts: i64,
+    pub last_used_date_ts: i64, // the last refresh of the access token
+    pub expiration_date_ts: i64,
+}
+
+impl Session {
+    pub fn new(
+        user_id: ObjectId,
+        refresh_token_hash: String,
+        ttl: time::Duration,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    ) -> Session {
+        Session {
+            id: None,
+            user_id,
+            refresh_token_hash,
+            used_refresh_token_hashes: vec![],
+            revoked: false,
+            user_agent,
+            ip,
+            creation_date: DateTime::now(),
+            last_used_date: DateTime::now(),
+            expiration_date: Session::get_expiration_date(ttl),
+        }
+    }
+    pub fn get_expiration_date(ttl: time::Duration) -> DateTime {
+        DateTime::from_millis(DateTime::now().timestamp_millis() + ttl.whole_milliseconds() as i64)
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expiration_date < DateTime::now()
+    }
+    pub fn check_refresh_token(&self, refresh_token_hash: &str) -> RefreshTokenCheck {
+        if self.revoked || self.is_expired() {
+            RefreshTokenCheck::Inactive
+        } else if self.refresh_token_hash == refresh_token_hash {
+            RefreshTokenCheck::Current
+        } else {
+            RefreshTokenCheck::Reused
+        }
+    }
+    /// Replaces the current refresh token, the replaced one is remembered to detect its reuse.
+    pub fn rotate_refresh_token(
+        &mut self,
+        new_refresh_token_hash: String,
+        new_expiration_date: DateTime,
+        now: DateTime,
+    ) {
+        let used_refresh_token_hash =
+            std::mem::replace(&mut self.refresh_token_hash, new_refresh_token_hash);
+        self.used_refresh_token_hashes.push(used_refresh_token_hash);
+        self.last_used_date = now;
+        self.expiration_date = new_expiration_date;
+    }
+    pub fn get_info(&self, current_session_id: Option<ObjectId>) -> SessionInfo {
+        SessionInfo {
+            id: self.id.unwrap(),
+            user_agent: self.user_agent.clone(),
+            ip: self.ip.clone(),
+            current: self.id == current_session_id,
+            creation_date_ts: self.creation_date.timestamp_millis(),
+            last_used_date_ts: self.last_used_date.timestamp_millis(),
+            expiration_date_ts: self.expiration_date.timestamp_millis(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_session() -> Session {
+        Session::new(
+            ObjectId::new(),
+            "first".to_string(),
+            time::Duration::days(30),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_is_expired() {
+        let mut session = get_session();
+        assert!(!session.is_expired());
+
+        session.expiration_date = DateTime::from_millis(DateTime::now().timestamp_millis() - 1);
+        assert!(session.is_expired());
+    }
+
+    #[test]
+    fn test_check_rotated_refresh_token() {
+        let mut session = get_session();
+        assert_eq!(
+            session.check_refresh_token("first"),
+            RefreshTokenCheck::Current
+        );
+
+        let new_expiration_date = Session::get_expiration_date(time::Duration::days(30));
+        session.rotate_refresh_token("second".to_string(), new_expiration_date, DateTime::now());
+        assert_eq!(session.expiration_date, new_expiration_date);
+        assert_eq!(session.used_refresh_token_hashes, vec!["first".to_string()]);
+        assert_eq!(
+            session.check_refresh_token("second"),
+            RefreshTokenCheck::Current
+        );
+        // presenting the rotated token again is treated as theft
+        assert_eq!(
+            session.check_refresh_token("first"),
+            RefreshTokenCheck::Reused
+        );
+    }
+
+    #[test]
+    fn test_check_refresh_token_of_inactive_session() {
+        let mut revoked_session = get_session();
+        revoked_session.revoked = true;
+        assert_eq!(
+            revoked_session.check_refresh_token("first"),
+            RefreshTokenCheck::Inactive
+        );
+
+        let mut expired_session = get_session();
+        expired_session.expiration_date = DateTime::from_millis(0);
+        assert_eq!(
+            expired_session.check_refresh_token("first"),
+            RefreshTokenCheck::Inactive
+        );
+    }
+}
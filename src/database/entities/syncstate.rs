@@ -37,6 +37,7 @@ pub enum SyncStateAction {
     Rename, // dir, file
     Move,   // dir, file (list file info by id is required to get info about current folder)
     Delete, // dir, file, user
+    Update, // file (the content has been replaced)
 }
 
 impl SyncState {
@@ -53,6 +54,7 @@ impl SyncState {
             SyncStateAction::Rename => "rename".to_string(),
             SyncStateAction::Move => "move".to_string(),
             SyncStateAction::Delete => "delete".to_string(),
+            SyncStateAction::Update => "update".to_string(),
         }
     }
     pub fn new(
@@ -1,8 +1,17 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use ring::test::from_hex;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use time::OffsetDateTime;
 
 use crate::database::database::MyDBModel;
+use crate::jwt_utils::hash_opaque_token;
+use crate::totp;
+use crate::SETTINGS;
 use strum_macros::AsRefStr;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,6 +24,33 @@ pub struct User {
     pub pw_hash: String,
     pub role: Role,
     pub root_dir_id: Option<ObjectId>,
+    #[serde(default)]
+    pub totp_secret: Option<String>, // base32 encoded, set on enrollment
+    #[serde(default)]
+    pub totp_enabled: bool, // true after the enrollment has been confirmed with a valid code
+    #[serde(default)]
+    pub totp_last_used_step: Option<i64>,
+    #[serde(default)]
+    pub totp_recovery_code_hashes: Vec<String>,
+    // users created before email verification existed are treated as verified
+    #[serde(default = "default_email_verified")]
+    pub email_verified: bool,
+    #[serde(default)]
+    pub oidc_subject: Option<String>, // `sub` of the linked identity provider account
+    #[serde(default)]
+    pub ldap_dn: Option<String>, // dn of the linked directory entry
+    #[serde(default)]
+    pub disabled: bool, // disabled users cannot log in
+    #[serde(default)]
+    pub deletion_date: Option<DateTime>, // the account is deleted after this date, until then it can be restored
+    #[serde(default)]
+    pub used_bytes: i64, // only changed atomically by the UserDAO
+    #[serde(default)]
+    pub quota_bytes: Option<i64>, // the configured default if not set, 0 is unlimited
+}
+
+fn default_email_verified() -> bool {
+    true
 }
 
 impl MyDBModel for User {
@@ -23,7 +59,7 @@ impl MyDBModel for User {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, AsRefStr)]
+#[derive(Debug, Clone, Serialize, Deserialize, AsRefStr)]
 pub enum Role {
     Admin,
     BaseUser,
@@ -31,13 +67,42 @@ pub enum Role {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserLogin {
-    pub email: String,
+    pub email: String, // or the username of a directory account
     pub pw_hash: String,
+    pub password: Option<String>, // plain password, only required for directory logins
 }
 
 #[derive(Serialize)]
 pub struct LoginResponse {
     pub jwt: String,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize)]
+pub struct MfaPendingResponse {
+    pub mfa_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserLoginMfa {
+    pub mfa_token: String,
+    pub code: String, // totp code or recovery code
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollResponse {
+    pub secret: String,
+    pub provisioning_uri: String,
+}
+
+#[derive(Serialize)]
+pub struct TotpConfirmResponse {
+    pub recovery_codes: Vec<String>,
 }
 
 #[derive(Serialize)]
@@ -51,9 +116,132 @@ pub struct UserRegister {
     pub lastname: String,
     pub email: String,
     pub pw_hash: String,
+    pub invitation_code: Option<String>, // required if public registration is disabled
+}
+
+/// The user as shown to the user and to administrators, without credentials.
+#[derive(Serialize)]
+pub struct UserInfo {
+    pub id: ObjectId,
+    pub firstname: String,
+    pub lastname: String,
+    pub email: String,
+    pub role: Role,
+    pub root_dir_id: Option<ObjectId>,
+    pub email_verified: bool,
+    pub totp_enabled: bool,
+    pub oidc_linked: bool,
+    pub ldap_linked: bool,
+    pub disabled: bool,
+    pub deletion_date_ts: Option<i64>,
+    pub used_bytes: i64,
+    pub quota_bytes: Option<i64>, // unlimited if not set
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserSearch {
+    pub search: Option<String>, // part of the firstname, lastname or email
+    pub page: Option<u64>,      // starts with 0
+    pub page_size: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct UserSearchResponse {
+    pub users: Vec<UserInfo>,
+    pub total: u64,
+    pub page: u64,
+    pub page_size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserId {
+    pub id: ObjectId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserDelete {
+    pub id: ObjectId,
+    #[serde(default)]
+    pub immediately: bool, // skip the grace period
+}
+
+/// The current credentials, required to delete the own account.
+#[derive(Debug, Deserialize)]
+pub struct AccountDelete {
+    pub pw_hash: String,
+}
+
+/// The credentials of an account scheduled for deletion, required to restore it.
+#[derive(Debug, Deserialize)]
+pub struct AccountRestore {
+    pub email: String,
+    pub pw_hash: String,
+    pub code: Option<String>, // totp or recovery code, only required with two-factor authentication
+}
+
+/// Changes of the own profile, fields that are not set are kept.
+#[derive(Debug, Deserialize)]
+pub struct UserUpdate {
+    pub firstname: Option<String>,
+    pub lastname: Option<String>,
+    pub email: Option<String>,   // has to be verified again
+    pub pw_hash: Option<String>, // the current password, required to change the email
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordChange {
+    pub pw_hash: String, // the current password
+    pub new_pw_hash: String,
+}
+
+#[derive(Serialize)]
+pub struct AccountDeleteResponse {
+    pub deletion_date_ts: Option<i64>, // not set if the account has been deleted immediately
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserRoleUpdate {
+    pub id: ObjectId,
+    pub role: Role,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserQuotaUpdate {
+    pub id: ObjectId,
+    pub quota_bytes: Option<i64>, // use the configured default if not set, 0 is unlimited
+}
+
+#[derive(Serialize)]
+pub struct UserUsage {
+    pub file_count: u64,
+    pub directory_count: u64,
+    pub used_bytes: u64,
 }
 
 impl User {
+    pub fn new(firstname: String, lastname: String, email: String, pw_hash: String) -> User {
+        User {
+            id: None,
+            firstname,
+            lastname,
+            email,
+            pw_hash,
+            role: Role::BaseUser,
+            root_dir_id: None,
+            totp_secret: None,
+            totp_enabled: false,
+            totp_last_used_step: None,
+            totp_recovery_code_hashes: vec![],
+            email_verified: false,
+            oidc_subject: None,
+            ldap_dn: None,
+            disabled: false,
+            deletion_date: None,
+            used_bytes: 0,
+            quota_bytes: None,
+        }
+    }
+
     pub fn is_valid_hash_design(hash: &str) -> bool {
         let pw_bytes_res = from_hex(hash);
 
@@ -61,6 +249,115 @@ impl User {
         // sha512 requires 64 bytes = 512 bit
         pw_bytes_res.is_ok() && pw_bytes_res.to_owned().unwrap().len() >= 32
     }
+
+    /// Runs the client supplied password hash through Argon2id with a random per-user salt
+    /// and returns the resulting PHC string that gets stored as `pw_hash`.
+    pub fn hash_password(client_pw_hash: &str) -> actix_web::Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+
+        Ok(Argon2::default()
+            .hash_password(client_pw_hash.as_bytes(), &salt)
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .to_string())
+    }
+
+    /// Checks the client supplied password hash against the stored one.
+    /// Legacy records still holding the plain client hash are compared in constant time.
+    pub fn verify_password(&self, client_pw_hash: &str) -> bool {
+        if self.has_legacy_pw_hash() {
+            return bool::from(self.pw_hash.as_bytes().ct_eq(client_pw_hash.as_bytes()));
+        }
+
+        match PasswordHash::new(self.pw_hash.as_str()) {
+            Ok(parsed_hash) => Argon2::default()
+                .verify_password(client_pw_hash.as_bytes(), &parsed_hash)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Checks a totp code of the confirmed or pending enrollment and marks it as used.
+    pub fn verify_totp_code(&mut self, code: &str) -> bool {
+        if let Some(secret) = &self.totp_secret {
+            let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+            let last_used_step = self.totp_last_used_step.map(|step| step as u64);
+
+            if let Some(step) = totp::verify(secret, code, now, last_used_step) {
+                self.totp_last_used_step = Some(step as i64);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Checks a recovery code and removes it, so it can only be used once.
+    pub fn use_totp_recovery_code(&mut self, code: &str) -> bool {
+        let code_hash = User::hash_totp_recovery_code(code);
+        if let Some(index) = self
+            .totp_recovery_code_hashes
+            .iter()
+            .position(|hash| bool::from(hash.as_bytes().ct_eq(code_hash.as_bytes())))
+        {
+            self.totp_recovery_code_hashes.remove(index);
+            return true;
+        }
+        false
+    }
+
+    pub fn hash_totp_recovery_code(code: &str) -> String {
+        hash_opaque_token(code.trim())
+    }
+
+    pub fn reset_totp(&mut self) {
+        self.totp_secret = None;
+        self.totp_enabled = false;
+        self.totp_last_used_step = None;
+        self.totp_recovery_code_hashes = vec![];
+    }
+
+    pub fn get_info(&self) -> UserInfo {
+        UserInfo {
+            id: self.id.unwrap(),
+            firstname: self.firstname.clone(),
+            lastname: self.lastname.clone(),
+            email: self.email.clone(),
+            role: self.role.clone(),
+            root_dir_id: self.root_dir_id,
+            email_verified: self.email_verified,
+            totp_enabled: self.totp_enabled,
+            oidc_linked: self.oidc_subject.is_some(),
+            ldap_linked: self.ldap_dn.is_some(),
+            disabled: self.disabled,
+            deletion_date_ts: self.deletion_date.map(|date| date.timestamp_millis()),
+            used_bytes: self.used_bytes,
+            quota_bytes: self.get_quota_bytes(),
+        }
+    }
+
+    /// Returns the quota of the user, None if the user has unlimited storage.
+    pub fn get_quota_bytes(&self) -> Option<i64> {
+        get_effective_quota_bytes(
+            self.quota_bytes,
+            SETTINGS.get().unwrap().default_user_quota_bytes,
+        )
+    }
+
+    /// Legacy records store the client hash as is, instead of a PHC formatted KDF output.
+    pub fn has_legacy_pw_hash(&self) -> bool {
+        !self.pw_hash.starts_with('$')
+    }
+
+    /// Linked to a directory entry or an identity provider account, which decides about the logins.
+    pub fn is_federated(&self) -> bool {
+        self.ldap_dn.is_some() || self.oidc_subject.is_some()
+    }
+}
+
+fn get_effective_quota_bytes(quota_bytes: Option<i64>, default_quota_bytes: i64) -> Option<i64> {
+    match quota_bytes.unwrap_or(default_quota_bytes) {
+        0 => None,
+        quota_bytes => Some(quota_bytes),
+    }
 }
 
 #[cfg(test)]
@@ -79,4 +376,57 @@ mod tests {
         assert_eq!(User::is_valid_hash_design(sha256), true);
         assert_eq!(User::is_valid_hash_design(sha512), true);
     }
+
+    #[test]
+    fn test_get_effective_quota_bytes() {
+        assert_eq!(get_effective_quota_bytes(None, 0), None);
+        assert_eq!(get_effective_quota_bytes(None, 1024), Some(1024));
+        assert_eq!(get_effective_quota_bytes(Some(2048), 1024), Some(2048));
+        assert_eq!(get_effective_quota_bytes(Some(0), 1024), None);
+    }
+
+    fn get_user_with_pw_hash(pw_hash: String) -> User {
+        User::new("".to_string(), "".to_string(), "".to_string(), pw_hash)
+    }
+
+    #[test]
+    fn test_verify_password() {
+        let sha256 = "1bc464c87c470882de2453b9978c4fa61dd680c30617b68c5ac1d4052ed39aef";
+        let other = "4fcdced7b0bdb6d4861c458c74bf0b8ace258c5d4fcdced7b0bdb6d4861c458c";
+
+        let user = get_user_with_pw_hash(User::hash_password(sha256).unwrap());
+        assert!(!user.has_legacy_pw_hash());
+        assert!(user.verify_password(sha256));
+        assert!(!user.verify_password(other));
+
+        // the same input must never result in the same stored hash (per-user salt)
+        assert_ne!(user.pw_hash, User::hash_password(sha256).unwrap());
+    }
+
+    #[test]
+    fn test_use_totp_recovery_code() {
+        let mut user = get_user_with_pw_hash("".to_string());
+        let recovery_codes = totp::generate_recovery_codes(2);
+        user.totp_recovery_code_hashes = recovery_codes
+            .iter()
+            .map(|code| hash_opaque_token(code))
+            .collect();
+
+        assert!(!user.use_totp_recovery_code("00000-00000"));
+        assert!(user.use_totp_recovery_code(recovery_codes[0].as_str()));
+        // recovery codes are single use
+        assert!(!user.use_totp_recovery_code(recovery_codes[0].as_str()));
+        assert_eq!(user.totp_recovery_code_hashes.len(), 1);
+    }
+
+    #[test]
+    fn test_verify_legacy_password() {
+        let sha256 = "1bc464c87c470882de2453b9978c4fa61dd680c30617b68c5ac1d4052ed39aef";
+        let other = "4fcdced7b0bdb6d4861c458c74bf0b8ace258c5d4fcdced7b0bdb6d4861c458c";
+
+        let user = get_user_with_pw_hash(sha256.to_string());
+        assert!(user.has_legacy_pw_hash());
+        assert!(user.verify_password(sha256));
+        assert!(!user.verify_password(other));
+    }
 }
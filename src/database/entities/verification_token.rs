@@ -0,0 +1,78 @@
+use crate::database::database::MyDBModel;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use strum_macros::AsRefStr;
+
+/// A single-use token sent to the user by mail. Only the hash of the token is stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationToken {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: ObjectId,
+    pub token_hash: String,
+    pub purpose: VerificationPurpose,
+    pub email: String, // the address the token has been sent to
+    pub expiration_date: DateTime,
+    pub creation_date: DateTime,
+}
+
+impl MyDBModel for VerificationToken {
+    fn type_name() -> &'static str {
+        "VerificationToken"
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, AsRefStr)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum VerificationPurpose {
+    EmailVerification,
+    PasswordReset,
+}
+
+impl VerificationPurpose {
+    pub fn get_ttl(&self) -> time::Duration {
+        match self {
+            VerificationPurpose::EmailVerification => time::Duration::days(2),
+            VerificationPurpose::PasswordReset => time::Duration::minutes(30),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailVerify {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    pub pw_hash: String,
+}
+
+impl VerificationToken {
+    pub fn new(
+        user_id: ObjectId,
+        token_hash: String,
+        purpose: VerificationPurpose,
+        email: String,
+    ) -> VerificationToken {
+        VerificationToken {
+            id: None,
+            user_id,
+            token_hash,
+            purpose,
+            email,
+            expiration_date: DateTime::from_millis(
+                DateTime::now().timestamp_millis() + purpose.get_ttl().whole_milliseconds() as i64,
+            ),
+            creation_date: DateTime::now(),
+        }
+    }
+}
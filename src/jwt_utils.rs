@@ -1,93 +1,493 @@
+use std::cmp::Reverse;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::ops::Add;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use actix_jwt_authc::*;
-use dashmap::DashSet;
-use futures::channel::{mpsc, mpsc::Sender};
-use futures::{SinkExt, Stream};
-use hmac::{Hmac, Mac};
+use anyhow::anyhow;
+use base64::alphabet::URL_SAFE;
+use base64::engine::fast_portable::{FastPortable, NO_PAD};
+use dashmap::DashMap;
 use jsonwebtoken::*;
 use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
 use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use sha2::Sha512;
-use tokio::sync::Mutex;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use tracing::{event, Level};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::invalidated_jwt_dao::InvalidatedJWTDAO;
+use crate::database::entities::invalidated_jwt::InvalidatedJWT;
+use crate::settings::Settings;
 
 pub const JWT_SIGNING_ALGO: Algorithm = Algorithm::HS512;
-type HmacSha512 = Hmac<Sha512>;
+const LEGACY_JWT_SECRET_KID: &str = "jwt_secret";
+const INVALIDATED_JWT_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+const URL_SAFE_ENGINE: FastPortable = FastPortable::from(&URL_SAFE, NO_PAD);
+
+/// A jwt signing key as it is stored in the keyring file or directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredJwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub secret: String, // base64 encoded hmac secret or ed25519 pkcs8 document
+    pub creation_date: i64,
+}
+
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub creation_date: i64,
+    encoding_key: EncodingKey, // encode and sign the jwt on login
+    decoding_key: DecodingKey, // check if the sign of an existing token is valid
+}
+
+// DER encoded PKCS#8 v1 header of an ed25519 private key, followed by the 32 byte seed
+const ED25519_PKCS8_V1_PREFIX: [u8; 16] = [
+    0x30, 0x2e, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04, 0x20,
+];
+
+/// All known jwt signing keys.
+/// The newest key signs new tokens, older (retired) keys are only used to verify existing tokens.
+pub struct JwtKeyring {
+    keys: Vec<JwtKey>,
+}
+
+impl StoredJwtKey {
+    pub fn generate(algorithm: Algorithm) -> anyhow::Result<Self> {
+        let sr = SystemRandom::new();
+        let secret = match algorithm {
+            Algorithm::HS512 => {
+                let mut randoms: [u8; 64] = [0; 64];
+                sr.fill(&mut randoms)
+                    .map_err(|_| anyhow!("failed to create random bytes for the jwt secret"))?;
+                base64::encode(randoms)
+            }
+            Algorithm::EdDSA => {
+                // a PKCS#8 v1 document only holds the private seed and is understood by every
+                // ring version in the dependency tree, unlike the v2 formats, which differ
+                let mut pkcs8 = ED25519_PKCS8_V1_PREFIX.to_vec();
+                let mut seed: [u8; 32] = [0; 32];
+                sr.fill(&mut seed)
+                    .map_err(|_| anyhow!("failed to generate an ed25519 key pair"))?;
+                pkcs8.extend_from_slice(&seed);
+                base64::encode(pkcs8)
+            }
+            _ => return Err(anyhow!("unsupported jwt signing algorithm {:?}", algorithm)),
+        };
 
-pub struct JwtSigningKeys {
-    pub encoding_key: EncodingKey, // encode and sign the jwt on login
-    decoding_key: DecodingKey,     // check if the sign of an existing token is valid
+        Ok(StoredJwtKey {
+            kid: ObjectId::new().to_hex(),
+            algorithm,
+            secret,
+            creation_date: OffsetDateTime::now_utc().unix_timestamp(),
+        })
+    }
 }
 
-impl JwtSigningKeys {
-    pub fn parse(secret: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let encoding_key = EncodingKey::from_base64_secret(secret).unwrap();
-        let decoding_key = DecodingKey::from_base64_secret(secret).unwrap();
+impl JwtKey {
+    pub fn parse(stored_key: &StoredJwtKey) -> anyhow::Result<Self> {
+        let (encoding_key, decoding_key) = match stored_key.algorithm {
+            Algorithm::HS512 => (
+                EncodingKey::from_base64_secret(stored_key.secret.as_str())?,
+                DecodingKey::from_base64_secret(stored_key.secret.as_str())?,
+            ),
+            Algorithm::EdDSA => {
+                let pkcs8 = base64::decode(stored_key.secret.as_str())?;
+                let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8.as_slice())
+                    .map_err(|e| anyhow!("invalid ed25519 key {}: {}", stored_key.kid, e))?;
+                (
+                    EncodingKey::from_ed_der(pkcs8.as_slice()),
+                    DecodingKey::from_ed_der(key_pair.public_key().as_ref()),
+                )
+            }
+            _ => {
+                return Err(anyhow!(
+                    "unsupported jwt signing algorithm {:?}",
+                    stored_key.algorithm
+                ))
+            }
+        };
 
-        Ok(JwtSigningKeys {
+        Ok(JwtKey {
+            kid: stored_key.kid.clone(),
+            algorithm: stored_key.algorithm,
+            creation_date: stored_key.creation_date,
             encoding_key,
             decoding_key,
         })
     }
-    pub fn generate() -> Result<Self, Box<dyn std::error::Error>> {
-        let mut randoms: [u8; 64] = [0; 64];
-        let sr = SystemRandom::new();
-        sr.fill(&mut randoms)
-            .expect("failed to create random bytes for the jwt secret");
+}
+
+impl JwtKeyring {
+    /// Loads the keyring configured in the settings and creates its first key if it is empty.
+    /// A long enough `jwt_secret` is still accepted as (oldest) key without kid.
+    pub fn load(settings: &Settings) -> anyhow::Result<Self> {
+        let keyring_path = Path::new(settings.jwt_keyring_path.as_str());
+        let mut stored_keys = Self::read_stored_keys(keyring_path)?;
+
+        if settings.jwt_secret.len() > 20 {
+            stored_keys.push(StoredJwtKey {
+                kid: LEGACY_JWT_SECRET_KID.to_string(),
+                algorithm: JWT_SIGNING_ALGO,
+                secret: settings.jwt_secret.clone(),
+                creation_date: 0,
+            });
+        } else if stored_keys.is_empty() {
+            event!(
+                Level::INFO,
+                "jwt keyring is empty, creating a first signing key"
+            );
+            stored_keys.push(Self::add_key(keyring_path, JWT_SIGNING_ALGO)?);
+        }
+
+        let mut keys = stored_keys
+            .iter()
+            .map(JwtKey::parse)
+            .collect::<anyhow::Result<Vec<JwtKey>>>()?;
+        keys.sort_by_key(|key| Reverse(key.creation_date));
 
-        let mac = HmacSha512::new_from_slice(&randoms).unwrap().to_owned();
-        let secret = base64::encode(mac.finalize().into_bytes());
-        println!("secret: {}", secret);
+        Ok(JwtKeyring { keys })
+    }
+
+    /// Reads the keys from a keyring file (json array of keys) or a keyring directory (one json file per key).
+    pub fn read_stored_keys(keyring_path: &Path) -> anyhow::Result<Vec<StoredJwtKey>> {
+        if keyring_path.is_dir() {
+            let mut stored_keys = Vec::new();
+            for entry in fs::read_dir(keyring_path)? {
+                let path = entry?.path();
+                if path.extension() == Some(OsStr::new("json")) {
+                    stored_keys.push(serde_json::from_str(fs::read_to_string(path)?.as_str())?);
+                }
+            }
+            return Ok(stored_keys);
+        }
+
+        if keyring_path.is_file() {
+            return Ok(serde_json::from_str(
+                fs::read_to_string(keyring_path)?.as_str(),
+            )?);
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// Generates a new key and stores it in the keyring, so it will be the active signing key after the next restart.
+    pub fn add_key(keyring_path: &Path, algorithm: Algorithm) -> anyhow::Result<StoredJwtKey> {
+        let stored_key = StoredJwtKey::generate(algorithm)?;
+
+        if keyring_path.is_dir() {
+            write_secret_file(
+                keyring_path
+                    .join(format!("{}.json", stored_key.kid))
+                    .as_path(),
+                serde_json::to_string_pretty(&stored_key)?.as_str(),
+            )?;
+        } else {
+            let mut stored_keys = Self::read_stored_keys(keyring_path)?;
+            stored_keys.push(stored_key.clone());
+            write_secret_file(
+                keyring_path,
+                serde_json::to_string_pretty(&stored_keys)?.as_str(),
+            )?;
+        }
+
+        Ok(stored_key)
+    }
 
-        JwtSigningKeys::parse(secret.as_str())
+    pub fn active_key(&self) -> &JwtKey {
+        &self.keys[0]
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let key = self.active_key();
+        let mut header = Header::new(key.algorithm);
+        if key.kid != LEGACY_JWT_SECRET_KID {
+            header.kid = Some(key.kid.clone());
+        }
+
+        encode(&header, claims, &key.encoding_key)
+    }
+
+    /// Verifies the token with the key referenced by its kid, tokens without kid are checked against all keys.
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> jsonwebtoken::errors::Result<T> {
+        let header = decode_header(token)?;
+        let mut last_error = jsonwebtoken::errors::ErrorKind::InvalidSignature.into();
+
+        for key in self.keys.iter().filter(|key| match &header.kid {
+            Some(kid) => key.kid.eq(kid),
+            None => true,
+        }) {
+            match decode::<T>(token, &key.decoding_key, &Validation::new(key.algorithm)) {
+                Ok(token_data) => return Ok(token_data.claims),
+                Err(e) => last_error = e,
+            }
+        }
+
+        Err(last_error)
     }
 }
 
+/// Writes the file readable by the owner only. The content is written to a temporary file first,
+/// so a crash cannot leave a truncated keyring behind.
+fn write_secret_file(path: &Path, content: &str) -> anyhow::Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid keyring path {:?}", path))?
+        .to_string_lossy();
+    // no .json extension, so a keyring directory never reads the temporary file
+    let temp_path = path.with_file_name(format!(".{}.tmp", file_name));
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&temp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// All expiration dates are unix timestamps with milliseconds, expired entries are pruned
+/// periodically, like the database removes them via the TTL index.
 #[derive(Clone)]
 pub struct InvalidatedJWTStore {
-    store: Arc<DashSet<JWT>>,
-    tx: Arc<Mutex<Sender<InvalidatedTokensEvent>>>,
+    // jwt -> expiration date
+    store: Arc<DashMap<JWT, i64>>,
+    // user id -> (issued before, expiration date), access tokens of the user issued before are invalid
+    user_revocations: Arc<DashMap<String, (i64, i64)>>,
+    // id of a revoked session -> expiration date, all access tokens of the session are invalid
+    session_revocations: Arc<DashMap<String, i64>>,
 }
 
 impl InvalidatedJWTStore {
-    /// Returns a [InvalidatedJWTStore] with a Stream of [InvalidatedTokensEvent]s
-    pub fn new_with_stream() -> (
-        InvalidatedJWTStore,
-        impl Stream<Item = InvalidatedTokensEvent>,
-    ) {
-        let invalidated = Arc::new(DashSet::new());
-        let (tx, rx) = mpsc::channel(100);
-        let tx_to_hold = Arc::new(Mutex::new(tx));
-        (
-            InvalidatedJWTStore {
-                store: invalidated,
-                tx: tx_to_hold,
-            },
-            rx,
-        )
+    pub fn new() -> InvalidatedJWTStore {
+        InvalidatedJWTStore {
+            store: Arc::new(DashMap::new()),
+            user_revocations: Arc::new(DashMap::new()),
+            session_revocations: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub fn contains(&self, jwt: &JWT) -> bool {
+        self.store.contains_key(jwt)
+    }
+
+    /// Checks if all tokens of the user have been revoked after these claims were issued,
+    /// or if the session of the claims has been revoked.
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        if let Some(session_id) = &claims.sid {
+            if self.session_revocations.contains_key(session_id) {
+                return true;
+            }
+        }
+        match self.user_revocations.get(&claims.sub) {
+            Some(revocation) => claims.get_issued_at_millis() < revocation.0,
+            None => false,
+        }
+    }
+
+    /// Revokes all access tokens issued to the user so far, e.g. to log the user out everywhere.
+    pub async fn invalidate_all_for_user(&self, user_id: ObjectId) -> actix_web::Result<()> {
+        let mut invalidated_jwt = InvalidatedJWT::new_for_all_of_user(user_id, get_jwt_ttl().0);
+        InvalidatedJWTDAO::insert(&mut invalidated_jwt).await?;
+
+        self.insert(invalidated_jwt);
+        Ok(())
+    }
+
+    /// Revokes all access tokens issued for the session, e.g. when the user logs out a device.
+    pub async fn invalidate_session(
+        &self,
+        user_id: ObjectId,
+        session_id: ObjectId,
+    ) -> actix_web::Result<()> {
+        let mut invalidated_jwt =
+            InvalidatedJWT::new_for_session(user_id, session_id, get_jwt_ttl().0);
+        InvalidatedJWTDAO::insert(&mut invalidated_jwt).await?;
+
+        self.insert(invalidated_jwt);
+        Ok(())
+    }
+
+    fn insert(&self, invalidated_jwt: InvalidatedJWT) {
+        let expiration_date = invalidated_jwt.expiration_date.timestamp_millis();
+        if let Some(session_id) = invalidated_jwt.session_id {
+            self.session_revocations
+                .insert(session_id.to_string(), expiration_date);
+            return;
+        }
+        match invalidated_jwt.issued_before {
+            Some(issued_before) => {
+                let issued_before = issued_before.timestamp_millis();
+                let mut revocation = self
+                    .user_revocations
+                    .entry(invalidated_jwt.user_id.to_string())
+                    .or_insert((issued_before, expiration_date));
+                if revocation.0 < issued_before {
+                    *revocation = (issued_before, expiration_date);
+                }
+            }
+            None => {
+                self.store.insert(JWT(invalidated_jwt.jwt), expiration_date);
+            }
+        }
+    }
+
+    /// Forgets the entries, whose tokens have expired anyway.
+    fn prune(&self, now: DateTime) {
+        let now = now.timestamp_millis();
+        self.store
+            .retain(|_, expiration_date| *expiration_date > now);
+        self.user_revocations
+            .retain(|_, (_, expiration_date)| *expiration_date > now);
+        self.session_revocations
+            .retain(|_, expiration_date| *expiration_date > now);
     }
 
     pub async fn add_to_invalidated(&self, authenticated: Authenticated<Claims>) -> bool {
-        self.store.insert(authenticated.jwt.clone());
-        let mut tx = self.tx.lock().await;
-        if let Err(_e) = tx
-            .send(InvalidatedTokensEvent::Add(authenticated.jwt))
-            .await
+        if let Err(e) = InvalidatedJWTDAO::insert(&mut InvalidatedJWT::new(
+            extract_user_oid(&authenticated),
+            authenticated.jwt.0.clone(),
+            authenticated.claims.exp,
+        ))
+        .await
         {
-            #[cfg(feature = "tracing")]
-            error!(error = ?_e, "Failed to send update on adding to invalidated");
+            event!(Level::ERROR, "Failed to persist invalidated jwt: {:?}", e);
             return false;
         }
+
+        self.store
+            .insert(authenticated.jwt, authenticated.claims.exp as i64 * 1000);
         true
     }
+
+    /// Loads all persisted and still valid invalidated tokens into the auth middleware.
+    pub async fn load_from_database(&self) -> actix_web::Result<()> {
+        InvalidatedJWTDAO::create_expiration_index().await?;
+
+        for invalidated_jwt in
+            InvalidatedJWTDAO::get_created_since(DateTime::from_millis(0)).await?
+        {
+            self.insert(invalidated_jwt);
+        }
+        Ok(())
+    }
+
+    /// Periodically fetches tokens invalidated by other server instances.
+    pub fn spawn_database_sync(&self) {
+        let invalidated_jwt_store = self.clone();
+
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(INVALIDATED_JWT_SYNC_INTERVAL);
+            let mut last_sync = DateTime::now();
+
+            loop {
+                interval.tick().await;
+                invalidated_jwt_store.prune(DateTime::now());
+
+                // overlap the windows a bit, to not miss tokens invalidated during the last query
+                let sync_start = DateTime::now();
+                let since = DateTime::from_millis(
+                    last_sync.timestamp_millis()
+                        - 2 * INVALIDATED_JWT_SYNC_INTERVAL.as_millis() as i64,
+                );
+
+                match InvalidatedJWTDAO::get_created_since(since).await {
+                    Ok(invalidated_jwts) => {
+                        for invalidated_jwt in invalidated_jwts {
+                            invalidated_jwt_store.insert(invalidated_jwt);
+                        }
+                        last_sync = sync_start;
+                    }
+                    Err(e) => event!(Level::WARN, "Failed to sync invalidated jwts: {:?}", e),
+                }
+            }
+        });
+    }
 }
 
 pub fn get_jwt_ttl() -> JWTTtl {
     JWTTtl(time::Duration::hours(1))
 }
 
+pub fn get_refresh_token_ttl() -> RefreshTokenTtl {
+    RefreshTokenTtl(time::Duration::days(30))
+}
+
+/// Creates and signs a new access token for the given user and login session.
+pub fn create_jwt(
+    user_id: ObjectId,
+    root_dir_id: ObjectId,
+    session_id: ObjectId,
+    jwt_keyring: &JwtKeyring,
+    jwt_ttl: &JWTTtl,
+) -> actix_web::Result<String> {
+    let now = OffsetDateTime::now_utc();
+    let iat = now.unix_timestamp() as usize;
+    let expires_at = now.add(jwt_ttl.0);
+    let exp = expires_at.unix_timestamp() as usize;
+
+    let jwt_claims = Claims {
+        iat,
+        iat_ms: Some(get_unix_timestamp_millis(now)),
+        exp,
+        sub: user_id.to_string(),
+        thunder_root_dir_id: root_dir_id,
+        sid: Some(session_id.to_string()),
+    };
+    jwt_keyring
+        .encode(&jwt_claims)
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+/// Creates a short-lived token, that has to be exchanged together with a second factor for a real access token.
+pub fn create_mfa_pending_jwt(
+    user_id: ObjectId,
+    jwt_keyring: &JwtKeyring,
+) -> actix_web::Result<String> {
+    let iat = OffsetDateTime::now_utc().unix_timestamp() as usize;
+    let exp = OffsetDateTime::now_utc()
+        .add(time::Duration::minutes(5))
+        .unix_timestamp() as usize;
+
+    jwt_keyring
+        .encode(&MfaPendingClaims {
+            exp,
+            iat,
+            sub: user_id.to_string(),
+            mfa_pending: true,
+        })
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+/// Generates a random, url safe token (e.g. a refresh token) that is handed out to the client once.
+pub fn generate_opaque_token() -> String {
+    let mut randoms: [u8; 32] = [0; 32];
+    SystemRandom::new()
+        .fill(&mut randoms)
+        .expect("failed to create random bytes for an opaque token");
+
+    base64::encode_engine(randoms, &URL_SAFE_ENGINE)
+}
+
+/// Opaque tokens are only stored as sha256 hash, so a database leak does not leak usable tokens.
+pub fn hash_opaque_token(token: &str) -> String {
+    base64::encode_engine(Sha256::digest(token.as_bytes()), &URL_SAFE_ENGINE)
+}
+
 pub fn extract_user_oid(authenticated: &Authenticated<Claims>) -> ObjectId {
     ObjectId::from_str(authenticated.claims.sub.as_str())
         .expect("could not extract user id from authenticated")
@@ -96,20 +496,253 @@ pub fn extract_user_oid(authenticated: &Authenticated<Claims>) -> ObjectId {
 #[derive(Clone, Copy)]
 pub struct JWTTtl(pub time::Duration);
 
+#[derive(Clone, Copy)]
+pub struct RefreshTokenTtl(pub time::Duration);
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct Claims {
     pub exp: usize,
     pub iat: usize,
+    // iat with milliseconds, so a login right after a revocation of all tokens is not revoked as well
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
     pub sub: String,
     pub thunder_root_dir_id: ObjectId,
+    // the login session, not set for api tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
 }
 
-pub fn get_auth_middleware_settings(
-    jwt_signing_keys: &JwtSigningKeys,
-) -> AuthenticateMiddlewareSettings {
-    AuthenticateMiddlewareSettings {
-        jwt_decoding_key: jwt_signing_keys.decoding_key.clone(),
-        jwt_authorization_header_prefixes: Some(vec!["Bearer".to_string()]),
-        jwt_validator: Validation::new(JWT_SIGNING_ALGO),
+impl Claims {
+    /// Tokens issued before `iat_ms` existed only know the second.
+    pub fn get_issued_at_millis(&self) -> i64 {
+        self.iat_ms.unwrap_or(self.iat as i64 * 1000)
+    }
+}
+
+pub fn get_unix_timestamp_millis(date: OffsetDateTime) -> i64 {
+    (date.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
+/// Claims of a login waiting for the second factor.
+/// They can never be decoded as [Claims], so the token cannot be used as access token.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct MfaPendingClaims {
+    pub exp: usize,
+    pub iat: usize,
+    pub sub: String,
+    pub mfa_pending: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_claims() -> Claims {
+        Claims {
+            exp: (OffsetDateTime::now_utc().unix_timestamp() + 60) as usize,
+            iat: OffsetDateTime::now_utc().unix_timestamp() as usize,
+            iat_ms: None,
+            sub: ObjectId::new().to_string(),
+            thunder_root_dir_id: ObjectId::new(),
+            sid: Some(ObjectId::new().to_string()),
+        }
+    }
+
+    fn get_keyring(stored_keys: &[StoredJwtKey]) -> JwtKeyring {
+        let mut keys: Vec<JwtKey> = stored_keys
+            .iter()
+            .map(|k| JwtKey::parse(k).unwrap())
+            .collect();
+        keys.sort_by_key(|key| Reverse(key.creation_date));
+        JwtKeyring { keys }
+    }
+
+    #[test]
+    fn test_keyring_encode_decode() {
+        for algorithm in [Algorithm::HS512, Algorithm::EdDSA] {
+            let keyring = get_keyring(&[StoredJwtKey::generate(algorithm).unwrap()]);
+            let claims = get_claims();
+
+            let token = keyring.encode(&claims).unwrap();
+            assert_eq!(
+                decode_header(token.as_str()).unwrap().kid,
+                Some(keyring.active_key().kid.clone())
+            );
+            assert_eq!(keyring.decode::<Claims>(token.as_str()).unwrap(), claims);
+        }
+    }
+
+    #[test]
+    fn test_keyring_rotation() {
+        let mut retired_key = StoredJwtKey::generate(Algorithm::HS512).unwrap();
+        retired_key.creation_date -= 3600;
+        let retired_keyring = get_keyring(&[retired_key.clone()]);
+        let claims = get_claims();
+        let old_token = retired_keyring.encode(&claims).unwrap();
+
+        let active_key = StoredJwtKey::generate(Algorithm::EdDSA).unwrap();
+        let keyring = get_keyring(&[retired_key, active_key.clone()]);
+        assert_eq!(keyring.active_key().kid, active_key.kid);
+
+        // tokens signed by a retired key are still valid
+        assert_eq!(
+            keyring.decode::<Claims>(old_token.as_str()).unwrap(),
+            claims
+        );
+
+        // tokens signed by an unknown key are not
+        let unknown_keyring = get_keyring(&[StoredJwtKey::generate(Algorithm::HS512).unwrap()]);
+        let unknown_token = unknown_keyring.encode(&claims).unwrap();
+        assert!(keyring.decode::<Claims>(unknown_token.as_str()).is_err());
+    }
+
+    #[test]
+    fn test_add_key_is_only_readable_by_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("jwt-keyring-{}", ObjectId::new()));
+        fs::create_dir(&dir).unwrap();
+
+        let keyring_file = dir.join("keyring.json");
+        JwtKeyring::add_key(keyring_file.as_path(), Algorithm::HS512).unwrap();
+        JwtKeyring::add_key(keyring_file.as_path(), Algorithm::EdDSA).unwrap();
+        assert_eq!(
+            JwtKeyring::read_stored_keys(keyring_file.as_path())
+                .unwrap()
+                .len(),
+            2
+        );
+
+        let keyring_dir = dir.join("keys");
+        fs::create_dir(&keyring_dir).unwrap();
+        let stored_key = JwtKeyring::add_key(keyring_dir.as_path(), Algorithm::HS512).unwrap();
+        assert_eq!(
+            JwtKeyring::read_stored_keys(keyring_dir.as_path())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        for path in [
+            keyring_file,
+            keyring_dir.join(format!("{}.json", stored_key.kid)),
+        ] {
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_mfa_pending_jwt_is_no_access_token() {
+        let keyring = get_keyring(&[StoredJwtKey::generate(Algorithm::HS512).unwrap()]);
+        let user_id = ObjectId::new();
+
+        let mfa_token = create_mfa_pending_jwt(user_id, &keyring).unwrap();
+        assert!(keyring.decode::<Claims>(mfa_token.as_str()).is_err());
+        assert_eq!(
+            keyring
+                .decode::<MfaPendingClaims>(mfa_token.as_str())
+                .unwrap()
+                .sub,
+            user_id.to_string()
+        );
+
+        let access_token = keyring.encode(&get_claims()).unwrap();
+        assert!(keyring
+            .decode::<MfaPendingClaims>(access_token.as_str())
+            .is_err());
+    }
+
+    #[test]
+    fn test_revoke_all_tokens_of_user() {
+        let store = InvalidatedJWTStore::new();
+        let mut claims = get_claims();
+        claims.iat -= 10;
+        let other_claims = get_claims();
+
+        store.insert(InvalidatedJWT::new_for_all_of_user(
+            ObjectId::from_str(claims.sub.as_str()).unwrap(),
+            get_jwt_ttl().0,
+        ));
+        assert!(store.is_revoked(&claims));
+        assert!(!store.is_revoked(&other_claims));
+
+        // tokens issued after the revocation stay valid
+        claims.iat += 20;
+        assert!(!store.is_revoked(&claims));
+    }
+
+    #[test]
+    fn test_revoke_all_tokens_of_user_in_same_second() {
+        let store = InvalidatedJWTStore::new();
+        let mut claims = get_claims();
+        let invalidated_jwt = InvalidatedJWT::new_for_all_of_user(
+            ObjectId::from_str(claims.sub.as_str()).unwrap(),
+            get_jwt_ttl().0,
+        );
+        let revoked_at = invalidated_jwt.issued_before.unwrap().timestamp_millis();
+        store.insert(invalidated_jwt);
+
+        claims.iat = (revoked_at / 1000) as usize;
+        claims.iat_ms = Some(revoked_at - 1);
+        assert!(store.is_revoked(&claims));
+
+        // e.g. a new login right after logging out everywhere
+        claims.iat_ms = Some(revoked_at);
+        assert!(!store.is_revoked(&claims));
+        claims.iat_ms = Some(revoked_at + 1);
+        assert!(!store.is_revoked(&claims));
+    }
+
+    #[test]
+    fn test_revoke_tokens_of_session() {
+        let store = InvalidatedJWTStore::new();
+        let claims = get_claims();
+        let other_claims = get_claims();
+
+        store.insert(InvalidatedJWT::new_for_session(
+            ObjectId::from_str(claims.sub.as_str()).unwrap(),
+            ObjectId::from_str(claims.sid.as_ref().unwrap()).unwrap(),
+            get_jwt_ttl().0,
+        ));
+        assert!(store.is_revoked(&claims));
+        assert!(!store.is_revoked(&other_claims));
+    }
+
+    #[test]
+    fn test_prune_expired_revocations() {
+        let store = InvalidatedJWTStore::new();
+        let claims = get_claims();
+        let user_id = ObjectId::from_str(claims.sub.as_str()).unwrap();
+        let jwt = JWT("token".to_string());
+
+        store.insert(InvalidatedJWT::new(user_id, jwt.0.clone(), claims.exp));
+        store.insert(InvalidatedJWT::new_for_all_of_user(
+            user_id,
+            get_jwt_ttl().0,
+        ));
+        store.insert(InvalidatedJWT::new_for_session(
+            user_id,
+            ObjectId::from_str(claims.sid.as_ref().unwrap()).unwrap(),
+            get_jwt_ttl().0,
+        ));
+
+        store.prune(DateTime::now());
+        assert!(store.contains(&jwt));
+        assert!(store.is_revoked(&claims));
+
+        // the revoked tokens have expired anyway
+        let after_expiration = DateTime::from_millis(
+            DateTime::now().timestamp_millis() + get_jwt_ttl().0.whole_milliseconds() as i64 + 1000,
+        );
+        store.prune(after_expiration);
+        assert!(!store.contains(&jwt));
+        assert!(!store.is_revoked(&claims));
+        assert!(store.store.is_empty());
+        assert!(store.user_revocations.is_empty());
+        assert!(store.session_revocations.is_empty());
     }
 }
@@ -0,0 +1,108 @@
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use once_cell::sync::OnceCell;
+use tracing::{event, Level};
+
+use crate::mail::templates::MailTemplate;
+use crate::settings::Settings;
+
+static MAIL_TRANSPORT: OnceCell<MailTransport> = OnceCell::new();
+static MAIL_FROM: OnceCell<String> = OnceCell::new();
+static APP_NAME: OnceCell<String> = OnceCell::new();
+
+enum MailTransport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>), // writes every mail as .eml file, for development
+    Log,                                      // only logs mails, for development
+}
+
+pub struct Mailer {}
+
+impl Mailer {
+    pub fn init(settings: &Settings) -> anyhow::Result<()> {
+        let mail_settings = &settings.mail;
+
+        let transport = match mail_settings.transport.as_str() {
+            "smtp" => {
+                let mut builder = match mail_settings.smtp_encryption.as_str() {
+                    "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(
+                        mail_settings.smtp_host.as_str(),
+                    )?,
+                    "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(
+                        mail_settings.smtp_host.as_str(),
+                    )?,
+                    _ => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                        mail_settings.smtp_host.as_str(),
+                    ),
+                }
+                .port(mail_settings.smtp_port);
+
+                if !mail_settings.smtp_username.is_empty() {
+                    builder = builder.credentials(Credentials::new(
+                        mail_settings.smtp_username.clone(),
+                        mail_settings.smtp_password.clone(),
+                    ));
+                }
+                MailTransport::Smtp(builder.build())
+            }
+            "file" => {
+                std::fs::create_dir_all(&mail_settings.file_path)?;
+                MailTransport::File(AsyncFileTransport::<Tokio1Executor>::new(
+                    &mail_settings.file_path,
+                ))
+            }
+            _ => MailTransport::Log,
+        };
+
+        MAIL_TRANSPORT
+            .set(transport)
+            .map_err(|_| anyhow::anyhow!("mailer has already been initialized"))?;
+        MAIL_FROM.set(mail_settings.from.clone()).unwrap();
+        APP_NAME.set(settings.app_name.clone()).unwrap();
+        Ok(())
+    }
+
+    pub async fn send(to: &str, template: MailTemplate) -> actix_web::Result<()> {
+        let app_name = APP_NAME.get().unwrap();
+
+        let message = Message::builder()
+            .from(
+                MAIL_FROM
+                    .get()
+                    .unwrap()
+                    .parse()
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+            )
+            .to(to.parse().map_err(actix_web::error::ErrorBadRequest)?)
+            .subject(template.subject(app_name))
+            .header(ContentType::TEXT_PLAIN)
+            .body(template.body(app_name))
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        match MAIL_TRANSPORT.get().unwrap() {
+            MailTransport::Smtp(transport) => {
+                transport
+                    .send(message)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            MailTransport::File(transport) => {
+                transport
+                    .send(message)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            MailTransport::Log => {
+                event!(
+                    Level::INFO,
+                    "mail to {}:\n{}",
+                    to,
+                    String::from_utf8_lossy(message.formatted().as_slice())
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
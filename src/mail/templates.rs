@@ -0,0 +1,79 @@
+/// All mails sent by the server. The subject and body are rendered for the configured app name.
+pub enum MailTemplate {
+    EmailVerification {
+        firstname: String,
+        verification_link: String,
+    },
+    PasswordReset {
+        firstname: String,
+        reset_token: String,
+        valid_minutes: i64,
+    },
+}
+
+impl MailTemplate {
+    pub fn subject(&self, app_name: &str) -> String {
+        match self {
+            MailTemplate::EmailVerification { .. } => {
+                format!("{}: Please verify your email address", app_name)
+            }
+            MailTemplate::PasswordReset { .. } => format!("{}: Reset your password", app_name),
+        }
+    }
+
+    pub fn body(&self, app_name: &str) -> String {
+        match self {
+            MailTemplate::EmailVerification {
+                firstname,
+                verification_link,
+            } => format!(
+                "Hello {},\n\n\
+                please confirm your email address for your {} account by opening the following link:\n\n\
+                {}\n\n\
+                If you did not create an account, you can ignore this mail.\n",
+                firstname, app_name, verification_link
+            ),
+            MailTemplate::PasswordReset {
+                firstname,
+                reset_token,
+                valid_minutes,
+            } => format!(
+                "Hello {},\n\n\
+                a password reset has been requested for your {} account.\n\
+                Use the following reset code within the next {} minutes to set a new password:\n\n\
+                {}\n\n\
+                If you did not request a password reset, you can ignore this mail.\n",
+                firstname, app_name, valid_minutes, reset_token
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_templates() {
+        let template = MailTemplate::EmailVerification {
+            firstname: "Ada".to_string(),
+            verification_link: "http://localhost/verify?token=abc".to_string(),
+        };
+        assert_eq!(
+            template.subject("Thunderklaud"),
+            "Thunderklaud: Please verify your email address"
+        );
+        assert!(template.body("Thunderklaud").starts_with("Hello Ada,"));
+        assert!(template
+            .body("Thunderklaud")
+            .contains("http://localhost/verify?token=abc"));
+
+        let template = MailTemplate::PasswordReset {
+            firstname: "Ada".to_string(),
+            reset_token: "reset-me".to_string(),
+            valid_minutes: 60,
+        };
+        assert!(template.body("Thunderklaud").contains("reset-me"));
+        assert!(template.body("Thunderklaud").contains("60 minutes"));
+    }
+}
@@ -1,9 +1,15 @@
+use std::sync::Arc;
+
+use crate::auth_middleware::AuthenticateMiddlewareFactory;
+use crate::database::daos::login_throttle_dao::LoginThrottleDAO;
+use crate::database::daos::oidc_login_state_dao::OidcLoginStateDAO;
+use crate::database::daos::verification_token_dao::VerificationTokenDAO;
 use crate::jwt_utils::{
-    get_auth_middleware_settings, get_jwt_ttl, Claims, InvalidatedJWTStore, JwtSigningKeys,
+    get_jwt_ttl, get_refresh_token_ttl, Claims, InvalidatedJWTStore, JwtKeyring,
 };
+use crate::mail::mailer::Mailer;
 use crate::storage::storage_provider::StorageProvider;
 use actix_cors::Cors;
-use actix_jwt_authc::AuthenticateMiddlewareFactory;
 use actix_web::web::Data;
 use actix_web::{http, web, App, HttpServer};
 use anyhow::Result;
@@ -13,14 +19,27 @@ use tracing::{event, Level};
 
 extern crate strum_macros;
 
+mod account;
 mod archive;
+mod auth_middleware;
+mod authenticator;
 mod cmd;
 mod controller;
+mod copy;
 mod database;
 mod jwt_utils;
+mod mail;
+mod oidc;
+mod paths;
 mod pipe;
+mod quota;
 mod settings;
 mod storage;
+mod throttling;
+mod totp;
+mod trash;
+mod tus;
+mod versioning;
 
 static SETTINGS: OnceCell<settings::Settings> = OnceCell::new();
 
@@ -42,19 +61,33 @@ async fn main() -> Result<()> {
     event!(Level::INFO, "tracing_subscriber initialized in main");
 
     StorageProvider::init(settings)?;
+    Mailer::init(settings)?;
 
     cmd::process().await;
 
-    let jwt_signing_keys = if (&settings).jwt_secret.len() > 20 {
-        JwtSigningKeys::parse((&settings).jwt_secret.as_str()).unwrap()
-    } else {
-        JwtSigningKeys::generate().unwrap()
-    };
-    let auth_middleware_settings = get_auth_middleware_settings(&jwt_signing_keys);
+    let jwt_keyring = Arc::new(JwtKeyring::load(settings)?);
 
-    let (invalidated_jwt_store, stream) = InvalidatedJWTStore::new_with_stream();
+    let invalidated_jwt_store = InvalidatedJWTStore::new();
+    invalidated_jwt_store
+        .load_from_database()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not load invalidated jwts: {}", e))?;
+    invalidated_jwt_store.spawn_database_sync();
+    account::spawn_due_deletions(invalidated_jwt_store.clone());
+    tus::spawn_expired_upload_cleanup();
+    versioning::spawn_expired_version_cleanup();
+    trash::spawn_trash_purge();
+    VerificationTokenDAO::create_expiration_index()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not create verification token index: {}", e))?;
+    OidcLoginStateDAO::create_expiration_index()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not create oidc login state index: {}", e))?;
+    LoginThrottleDAO::create_indexes()
+        .await
+        .map_err(|e| anyhow::anyhow!("could not create login throttle indexes: {}", e))?;
     let auth_middleware_factory =
-        AuthenticateMiddlewareFactory::<Claims>::new(stream, auth_middleware_settings.clone());
+        AuthenticateMiddlewareFactory::new(jwt_keyring.clone(), invalidated_jwt_store.clone());
 
     HttpServer::new(move || {
         let allowed_cors_origins = (&settings).allowed_cors_origins.clone();
@@ -65,7 +98,9 @@ async fn main() -> Result<()> {
                     .iter()
                     .any(|allowed_origin| bytes_origin.eq((*allowed_origin).as_bytes()))
             })
-            .allowed_methods(vec!["GET", "POST", "DELETE", "PUT", "PATCH", "OPTIONS"])
+            .allowed_methods(vec![
+                "GET", "HEAD", "POST", "DELETE", "PUT", "PATCH", "OPTIONS",
+            ])
             .allowed_headers(vec![
                 http::header::ACCESS_CONTROL_ALLOW_ORIGIN,
                 http::header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
@@ -73,14 +108,31 @@ async fn main() -> Result<()> {
                 http::header::AUTHORIZATION,
                 http::header::CONTENT_TYPE,
                 http::header::VARY,
+                http::header::HeaderName::from_static("tus-resumable"),
+                http::header::HeaderName::from_static("upload-length"),
+                http::header::HeaderName::from_static("upload-offset"),
+                http::header::HeaderName::from_static("upload-metadata"),
+                http::header::HeaderName::from_static("upload-checksum"),
+            ])
+            // tus clients read the upload state from these headers
+            .expose_headers(vec![
+                http::header::LOCATION,
+                http::header::HeaderName::from_static("tus-resumable"),
+                http::header::HeaderName::from_static("tus-version"),
+                http::header::HeaderName::from_static("tus-extension"),
+                http::header::HeaderName::from_static("tus-checksum-algorithm"),
+                http::header::HeaderName::from_static("upload-length"),
+                http::header::HeaderName::from_static("upload-offset"),
+                http::header::HeaderName::from_static("upload-expires"),
             ])
             .supports_credentials()
             .max_age(60); // see https://fetch.spec.whatwg.org/#http-access-control-max-age
 
         App::new()
             .app_data(Data::new(invalidated_jwt_store.clone()))
-            .app_data(Data::new(jwt_signing_keys.encoding_key.clone()))
+            .app_data(Data::from(jwt_keyring.clone()))
             .app_data(Data::new(get_jwt_ttl()))
+            .app_data(Data::new(get_refresh_token_ttl()))
             .wrap(cors)
             .wrap(auth_middleware_factory.clone())
             .service(
@@ -88,13 +140,70 @@ async fn main() -> Result<()> {
                     .service(
                         web::scope("/user")
                             .route("/login", web::post().to(controller::user::login))
+                            .route("/login/mfa", web::post().to(controller::user::login_mfa))
+                            .route("/oidc/login", web::get().to(controller::oidc::login))
+                            .route("/oidc/callback", web::get().to(controller::oidc::callback))
                             .route("/logout", web::post().to(controller::user::logout))
+                            .route("/refresh", web::post().to(controller::user::refresh))
                             .route("/registration", web::post().to(controller::user::register))
-                            .route("/test", web::get().to(controller::user::test))
+                            .route(
+                                "/email/verify",
+                                web::get().to(controller::user::verify_email),
+                            )
+                            .route(
+                                "/email/verify/resend",
+                                web::post().to(controller::user::resend_verification_email),
+                            )
+                            .route(
+                                "/password-reset",
+                                web::post().to(controller::user::request_password_reset),
+                            )
+                            .route(
+                                "/password-reset/confirm",
+                                web::post().to(controller::user::confirm_password_reset),
+                            )
+                            .route("/me", web::get().to(controller::user::get_me))
+                            .route("/me", web::patch().to(controller::user::update_me))
+                            .route(
+                                "/password",
+                                web::post().to(controller::user::change_password),
+                            )
+                            .route(
+                                "/account",
+                                web::delete().to(controller::user::delete_account),
+                            )
+                            .route(
+                                "/account/restore",
+                                web::post().to(controller::user::restore_account),
+                            )
                             .route("/syncstate", web::get().to(controller::syncstate::get))
                             .route(
                                 "/shares",
                                 web::get().to(controller::share::get_share_infos_for_user),
+                            )
+                            .route("/sessions", web::get().to(controller::session::get_all))
+                            .route(
+                                "/sessions",
+                                web::delete().to(controller::session::delete_all),
+                            )
+                            .route(
+                                "/sessions/{id}",
+                                web::delete().to(controller::session::delete),
+                            )
+                            .route("/tokens", web::post().to(controller::api_token::create))
+                            .route("/tokens", web::get().to(controller::api_token::get_all))
+                            .route("/tokens", web::delete().to(controller::api_token::delete))
+                            .route(
+                                "/totp/enroll",
+                                web::post().to(controller::user::enroll_totp),
+                            )
+                            .route(
+                                "/totp/confirm",
+                                web::post().to(controller::user::confirm_totp),
+                            )
+                            .route(
+                                "/totp/disable",
+                                web::post().to(controller::user::disable_totp),
                             ),
                     )
                     .service(
@@ -106,17 +215,85 @@ async fn main() -> Result<()> {
                                 web::delete().to(controller::directory::delete),
                             )
                             .route("/directory", web::get().to(controller::directory::get))
+                            .route(
+                                "/directory/ancestors",
+                                web::get().to(controller::directory::get_ancestors),
+                            )
+                            .route(
+                                "/directory/copy",
+                                web::post().to(controller::directory::copy),
+                            )
+                            .route("/file/copy", web::post().to(controller::file::copy))
                             .route("/file", web::put().to(controller::file::multi_upload))
                             .route("/file", web::patch().to(controller::file::update))
                             .route("/file", web::delete().to(controller::file::delete))
+                            .route(
+                                "/file/versions",
+                                web::get().to(controller::file_version::get_all),
+                            )
+                            .route(
+                                "/file/version",
+                                web::delete().to(controller::file_version::delete),
+                            )
+                            .route(
+                                "/file/version/restore",
+                                web::post().to(controller::file_version::restore),
+                            )
+                            // the bare routes address the root directory
+                            .route("/path", web::get().to(controller::path::get))
+                            .route("/path", web::put().to(controller::path::upload))
+                            .route("/path/{path:.*}", web::get().to(controller::path::get))
+                            .route("/path/{path:.*}", web::put().to(controller::path::upload))
+                            .service(
+                                web::scope("/trash")
+                                    .route("", web::get().to(controller::trash::get))
+                                    .route("", web::delete().to(controller::trash::empty))
+                                    .route(
+                                        "/file/restore",
+                                        web::post().to(controller::trash::restore_file),
+                                    )
+                                    .route(
+                                        "/directory/restore",
+                                        web::post().to(controller::trash::restore_directory),
+                                    )
+                                    .route(
+                                        "/file",
+                                        web::delete().to(controller::trash::delete_file),
+                                    )
+                                    .route(
+                                        "/directory",
+                                        web::delete().to(controller::trash::delete_directory),
+                                    ),
+                            )
+                            .service(
+                                web::scope("/tus")
+                                    .route(
+                                        "",
+                                        web::method(http::Method::OPTIONS)
+                                            .to(controller::tus::options),
+                                    )
+                                    .route("", web::post().to(controller::tus::create))
+                                    .route("/{uuid}", web::head().to(controller::tus::head))
+                                    .route("/{uuid}", web::patch().to(controller::tus::patch))
+                                    .route("/{uuid}", web::delete().to(controller::tus::delete)),
+                            )
                             .service(
                                 web::scope("/download")
                                     .route("/file", web::get().to(controller::file::get_single))
+                                    .route(
+                                        "/file/version",
+                                        web::get().to(controller::file_version::download),
+                                    )
                                     .route(
                                         "/directory",
                                         web::get().to(
                                             controller::directory::get_directory_archive_stream,
                                         ),
+                                    )
+                                    .route("/path", web::get().to(controller::path::download))
+                                    .route(
+                                        "/path/{path:.*}",
+                                        web::get().to(controller::path::download),
                                     ),
                             ),
                     )
@@ -141,6 +318,57 @@ async fn main() -> Result<()> {
                                 "/file",
                                 web::get().to(controller::share::get_share_infos_for_file),
                             ),
+                    )
+                    .service(
+                        web::scope("/admin")
+                            .route("/users", web::get().to(controller::admin::get_users))
+                            .route("/user", web::get().to(controller::admin::get_user))
+                            .route("/user", web::delete().to(controller::admin::delete_user))
+                            .route(
+                                "/user/restore",
+                                web::post().to(controller::admin::restore_user),
+                            )
+                            .route(
+                                "/user/usage",
+                                web::get().to(controller::admin::get_user_usage),
+                            )
+                            .route(
+                                "/user/role",
+                                web::patch().to(controller::admin::update_user_role),
+                            )
+                            .route(
+                                "/user/quota",
+                                web::patch().to(controller::admin::update_user_quota),
+                            )
+                            .route(
+                                "/user/disable",
+                                web::post().to(controller::admin::disable_user),
+                            )
+                            .route(
+                                "/user/enable",
+                                web::post().to(controller::admin::enable_user),
+                            )
+                            .route(
+                                "/user/logout",
+                                web::post().to(controller::admin::logout_user),
+                            )
+                            .route("/lockouts", web::get().to(controller::admin::get_lockouts))
+                            .route(
+                                "/lockouts",
+                                web::delete().to(controller::admin::delete_lockout),
+                            )
+                            .route(
+                                "/invitations",
+                                web::post().to(controller::invitation::create),
+                            )
+                            .route(
+                                "/invitations",
+                                web::get().to(controller::invitation::get_all),
+                            )
+                            .route(
+                                "/invitations",
+                                web::delete().to(controller::invitation::delete),
+                            ),
                     ),
             )
     })
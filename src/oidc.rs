@@ -0,0 +1,215 @@
+//! OpenID Connect authorization code flow with PKCE (RFC 7636) against the configured identity provider.
+use jsonwebtoken::jwk::{AlgorithmParameters, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+
+use crate::jwt_utils::hash_opaque_token;
+use crate::settings::Oidc;
+
+// only signature algorithms of rsa keys are supported, RS256 is mandatory for every provider
+const ALLOWED_ID_TOKEN_ALGORITHMS: [Algorithm; 3] =
+    [Algorithm::RS256, Algorithm::RS384, Algorithm::RS512];
+
+/// The parts of the provider metadata (`/.well-known/openid-configuration`) used by the login flow.
+#[derive(Debug, Deserialize)]
+pub struct OidcProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OidcTokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcIdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub given_name: Option<String>,
+    pub family_name: Option<String>,
+}
+
+/// Returns the S256 code challenge for a code verifier, which is the url-safe base64 encoded sha256 hash
+/// like the hashes of opaque tokens.
+pub fn get_pkce_challenge(code_verifier: &str) -> String {
+    hash_opaque_token(code_verifier)
+}
+
+pub async fn discover(oidc_settings: &Oidc) -> actix_web::Result<OidcProviderMetadata> {
+    let metadata: OidcProviderMetadata = reqwest::get(format!(
+        "{}/.well-known/openid-configuration",
+        oidc_settings.issuer_url.trim_end_matches('/')
+    ))
+    .await
+    .map_err(actix_web::error::ErrorBadGateway)?
+    .error_for_status()
+    .map_err(actix_web::error::ErrorBadGateway)?
+    .json()
+    .await
+    .map_err(actix_web::error::ErrorBadGateway)?;
+
+    if metadata.issuer.trim_end_matches('/') != oidc_settings.issuer_url.trim_end_matches('/') {
+        return Err(actix_web::error::ErrorBadGateway(
+            "The identity provider reported an unexpected issuer",
+        ));
+    }
+
+    Ok(metadata)
+}
+
+pub fn get_authorization_url(
+    metadata: &OidcProviderMetadata,
+    oidc_settings: &Oidc,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+) -> String {
+    format!(
+        "{}{}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}&code_challenge={}&code_challenge_method=S256",
+        metadata.authorization_endpoint,
+        if metadata.authorization_endpoint.contains('?') { '&' } else { '?' },
+        utf8_percent_encode(oidc_settings.client_id.as_str(), NON_ALPHANUMERIC),
+        utf8_percent_encode(oidc_settings.redirect_uri.as_str(), NON_ALPHANUMERIC),
+        utf8_percent_encode(oidc_settings.scopes.as_str(), NON_ALPHANUMERIC),
+        state,
+        nonce,
+        get_pkce_challenge(code_verifier)
+    )
+}
+
+/// Redeems the authorization code at the token endpoint and returns the verified id token claims.
+pub async fn exchange_code(
+    metadata: &OidcProviderMetadata,
+    oidc_settings: &Oidc,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+) -> actix_web::Result<OidcIdTokenClaims> {
+    let token_response: OidcTokenResponse = reqwest::Client::new()
+        .post(metadata.token_endpoint.as_str())
+        .basic_auth(
+            oidc_settings.client_id.as_str(),
+            Some(oidc_settings.client_secret.as_str()),
+        )
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", oidc_settings.redirect_uri.as_str()),
+            ("code_verifier", code_verifier),
+        ])
+        .send()
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?
+        .error_for_status()
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid authorization code"))?
+        .json()
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?;
+
+    let claims = verify_id_token(metadata, oidc_settings, token_response.id_token.as_str()).await?;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "The id token does not belong to this login",
+        ));
+    }
+
+    Ok(claims)
+}
+
+async fn verify_id_token(
+    metadata: &OidcProviderMetadata,
+    oidc_settings: &Oidc,
+    id_token: &str,
+) -> actix_web::Result<OidcIdTokenClaims> {
+    let header = decode_header(id_token).map_err(actix_web::error::ErrorUnauthorized)?;
+    if !ALLOWED_ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Unsupported id token signature algorithm",
+        ));
+    }
+
+    let jwks: JwkSet = reqwest::get(metadata.jwks_uri.as_str())
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?
+        .json()
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?;
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid.as_str()),
+        None => jwks.keys.first(),
+    }
+    .ok_or_else(|| actix_web::error::ErrorUnauthorized("Unknown id token signing key"))?;
+
+    let decoding_key = match &jwk.algorithm {
+        AlgorithmParameters::RSA(rsa) => DecodingKey::from_rsa_components(&rsa.n, &rsa.e)
+            .map_err(actix_web::error::ErrorBadGateway)?,
+        _ => {
+            return Err(actix_web::error::ErrorUnauthorized(
+                "Unsupported id token signing key",
+            ))
+        }
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[oidc_settings.client_id.as_str()]);
+    validation.set_issuer(&[metadata.issuer.as_str()]);
+
+    Ok(
+        decode::<OidcIdTokenClaims>(id_token, &decoding_key, &validation)
+            .map_err(actix_web::error::ErrorUnauthorized)?
+            .claims,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pkce_challenge() {
+        // RFC 7636 appendix B
+        assert_eq!(
+            get_pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn test_authorization_url() {
+        let metadata = OidcProviderMetadata {
+            issuer: "http://localhost:8090/default".to_string(),
+            authorization_endpoint: "http://localhost:8090/default/authorize".to_string(),
+            token_endpoint: "http://localhost:8090/default/token".to_string(),
+            jwks_uri: "http://localhost:8090/default/jwks".to_string(),
+        };
+        let oidc_settings = Oidc {
+            enabled: true,
+            issuer_url: metadata.issuer.clone(),
+            client_id: "thunder".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: "http://localhost:8000/callback".to_string(),
+            scopes: "openid email".to_string(),
+            create_users: true,
+        };
+
+        let url = get_authorization_url(&metadata, &oidc_settings, "state", "nonce", "verifier");
+        assert!(url.starts_with("http://localhost:8090/default/authorize?response_type=code&"));
+        assert!(url.contains("&redirect_uri=http%3A%2F%2Flocalhost%3A8000%2Fcallback&"));
+        assert!(url.contains("&scope=openid%20email&state=state&nonce=nonce&"));
+        assert!(url.ends_with(
+            format!(
+                "&code_challenge={}&code_challenge_method=S256",
+                get_pkce_challenge("verifier")
+            )
+            .as_str()
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+//! Resolution of slash separated paths like `/projects/2024/report.pdf` below the root directory
+//! of a user, so clients do not have to walk the tree one directory at a time.
+//! The directories and files in the trash are not part of any path.
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::entities::directory::Directory;
+use crate::database::entities::file::File;
+
+pub enum PathTarget {
+    Directory(Directory),
+    File(File),
+}
+
+/// Splits the path into its names, empty segments like in `a//b/` are ignored.
+pub fn split_path(path: &str) -> actix_web::Result<Vec<&str>> {
+    let mut names: Vec<&str> = Vec::new();
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        if name == "." || name == ".." {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Path segments . and .. are not supported",
+            ));
+        }
+        names.push(name);
+    }
+    Ok(names)
+}
+
+/// Returns the directory or the finished file at the path, an empty path is the root directory.
+/// If a directory and a file share the last name, the directory wins.
+pub async fn resolve(
+    root_dir_id: ObjectId,
+    user_id: ObjectId,
+    path: &str,
+) -> actix_web::Result<PathTarget> {
+    let names = split_path(path)?;
+    let root = get_root(root_dir_id, user_id).await?;
+
+    match names.split_last() {
+        Some((name, parent_names)) => {
+            let parent = walk(root, parent_names, false).await?;
+            if let Some(dir) = get_child_directory(&parent, name).await? {
+                return Ok(PathTarget::Directory(dir));
+            }
+            match parent.get_file_with_name(name).await {
+                Some(file) if file.finished => Ok(PathTarget::File(file)),
+                _ => Err(actix_web::error::ErrorNotFound("Path not found")),
+            }
+        }
+        None => Ok(PathTarget::Directory(root)),
+    }
+}
+
+/// Returns the directory at the path, with `create_missing` the missing directories get created.
+pub async fn resolve_directory(
+    root_dir_id: ObjectId,
+    user_id: ObjectId,
+    path: &str,
+    create_missing: bool,
+) -> actix_web::Result<Directory> {
+    let names = split_path(path)?;
+    let root = get_root(root_dir_id, user_id).await?;
+    walk(root, &names, create_missing).await
+}
+
+async fn get_root(root_dir_id: ObjectId, user_id: ObjectId) -> actix_web::Result<Directory> {
+    DirectoryDAO::get_with_user(root_dir_id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Root directory not found"))
+}
+
+async fn walk(
+    mut dir: Directory,
+    names: &[&str],
+    create_missing: bool,
+) -> actix_web::Result<Directory> {
+    for name in names {
+        dir = match get_child_directory(&dir, name).await? {
+            Some(child) => child,
+            None if create_missing => create_child_directory(&dir, name).await?,
+            None => {
+                return Err(actix_web::error::ErrorNotFound(format!(
+                    "Directory {} not found",
+                    name
+                )))
+            }
+        };
+    }
+    Ok(dir)
+}
+
+async fn get_child_directory(
+    parent: &Directory,
+    name: &str,
+) -> actix_web::Result<Option<Directory>> {
+    let children = DirectoryDAO::get_all_with_parent_id(parent.id).await?;
+    Ok(children.into_iter().find(|child| child.name == name))
+}
+
+async fn create_child_directory(parent: &Directory, name: &str) -> actix_web::Result<Directory> {
+    if parent.has_file_with_name(&name.to_string()).await {
+        return Err(actix_web::error::ErrorConflict(format!(
+            "{} is a file, not a directory",
+            name
+        )));
+    }
+
+    let mut dir = Directory {
+        id: None,
+        user_id: parent.user_id,
+        parent_id: parent.id,
+        name: name.to_string(),
+        creation_date: DateTime::now(),
+        child_ids: vec![],
+        deletion_date: None,
+        trashed_with: None,
+    };
+    // fails, if a parallel request created the directory in the meantime
+    DirectoryDAO::insert(&mut dir).await?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_path() {
+        assert_eq!(
+            split_path("/projects/2024/report.pdf").unwrap(),
+            vec!["projects", "2024", "report.pdf"]
+        );
+        assert_eq!(
+            split_path("projects//2024/").unwrap(),
+            vec!["projects", "2024"]
+        );
+        assert!(split_path("/").unwrap().is_empty());
+        assert!(split_path("").unwrap().is_empty());
+
+        assert!(split_path("/projects/../secret").is_err());
+        assert!(split_path("./projects").is_err());
+        // only whole segments are special
+        assert_eq!(
+            split_path("/.hidden/a..b").unwrap(),
+            vec![".hidden", "a..b"]
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Accounting of the storage used by each user against the user quota.
+use mongodb::bson::oid::ObjectId;
+
+use crate::account;
+use crate::database::daos::file_version_dao::FileVersionDAO;
+use crate::database::daos::user_dao::UserDAO;
+use crate::database::entities::file::File;
+use crate::database::entities::file_version::FileVersion;
+use crate::storage::storage_provider::StorageProvider;
+
+/// Counts the bytes of a new blob for the user, fails with 413 if the quota would be exceeded.
+pub async fn reserve(
+    user_id: ObjectId,
+    bytes: u64,
+    quota_bytes: Option<i64>,
+) -> actix_web::Result<()> {
+    if !UserDAO::reserve_used_bytes(user_id, bytes as i64, quota_bytes).await? {
+        return Err(get_quota_exceeded_error(quota_bytes));
+    }
+    Ok(())
+}
+
+pub fn get_quota_exceeded_error(quota_bytes: Option<i64>) -> actix_web::Error {
+    actix_web::error::ErrorPayloadTooLarge(format!(
+        "The upload exceeds the storage quota of {} bytes",
+        quota_bytes.unwrap_or_default()
+    ))
+}
+
+/// Books the difference, when a blob of the user gets replaced by a blob of another size.
+pub async fn replace(
+    user_id: ObjectId,
+    old_bytes: u64,
+    new_bytes: u64,
+    quota_bytes: Option<i64>,
+) -> actix_web::Result<()> {
+    if new_bytes > old_bytes {
+        return reserve(user_id, new_bytes - old_bytes, quota_bytes).await;
+    }
+    UserDAO::release_used_bytes(user_id, (old_bytes - new_bytes) as i64).await
+}
+
+/// Deletes the blob of the file and of its kept versions and gives their bytes back to the quota of the owner.
+pub async fn delete_file_blob(file: &File) -> actix_web::Result<()> {
+    if let Some(id) = file.id {
+        let file_versions = FileVersionDAO::get_all_for_file(id).await?;
+        for file_version in file_versions {
+            delete_version_blob(&file_version).await?;
+        }
+    }
+
+    let size = StorageProvider::get_file_size(file.uuid.clone());
+    StorageProvider::delete_file(file.uuid.clone())?;
+    UserDAO::release_used_bytes(file.user_id, size as i64).await
+}
+
+pub async fn delete_version_blob(file_version: &FileVersion) -> actix_web::Result<()> {
+    let size = StorageProvider::get_file_size(file_version.uuid.clone());
+    StorageProvider::delete_file(file_version.uuid.clone())?;
+    UserDAO::release_used_bytes(file_version.user_id, size as i64).await
+}
+
+/// Recomputes the used bytes of the user from the stored files, e.g. after the counter drifted.
+pub async fn recompute_used_bytes(user_id: ObjectId) -> actix_web::Result<i64> {
+    let used_bytes = account::get_usage(user_id).await?.used_bytes as i64;
+    UserDAO::set_used_bytes(user_id, used_bytes).await?;
+    Ok(used_bytes)
+}
@@ -15,6 +15,68 @@ pub struct Server {
     pub url: String,
     pub port: u16,
     pub address: String,
+    pub trusted_proxies: Vec<String>, // peer ips whose X-Forwarded-For header is trusted
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct Mail {
+    pub transport: String, // smtp, file or log
+    pub from: String,
+    pub require_email_verification: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: String,
+    pub smtp_password: String,
+    pub smtp_encryption: String, // none, starttls or tls
+    pub file_path: String,       // directory for the file transport
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct Oidc {
+    pub enabled: bool,
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String, // has to call /v1/user/oidc/callback with the code and state
+    pub scopes: String,       // space separated, has to contain openid
+    pub create_users: bool,   // create unknown users on their first login
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct Ldap {
+    pub enabled: bool,
+    pub url: String, // ldap:// or ldaps://
+    pub starttls: bool,
+    pub bind_dn_template: String, // e.g. uid={username},ou=people,dc=example,dc=org, search the user if empty
+    pub bind_dn: String,          // service account for the user search, anonymous if empty
+    pub bind_password: String,
+    pub user_search_base: String,
+    pub user_filter: String, // e.g. (|(uid={username})(mail={username}))
+    pub firstname_attribute: String,
+    pub lastname_attribute: String,
+    pub email_attribute: String,
+    pub admin_group_dn: String, // members get the admin role, roles are not synced if empty
+    pub group_member_attribute: String, // member or uniqueMember
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct Throttling {
+    pub account_max_failed_attempts: u32, // failures before an account gets locked
+    pub ip_max_failed_attempts: u32,      // failures before an ip gets locked
+    pub base_lockout_seconds: i64,        // doubled with every further failure
+    pub max_lockout_seconds: i64,
+    pub failed_attempts_ttl_minutes: i64, // failures are forgotten after this time without failure
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(unused)]
+pub struct Versioning {
+    pub max_versions_per_file: u32, // 0 keeps any number of versions
+    pub max_version_age_days: i64,  // 0 keeps the versions forever
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,9 +87,18 @@ pub struct Settings {
     pub debug: bool,
     pub database: Database,
     pub server: Server,
+    pub mail: Mail,
+    pub oidc: Oidc,
+    pub ldap: Ldap,
+    pub throttling: Throttling,
+    pub versioning: Versioning,
     pub jwt_secret: String,
+    pub jwt_keyring_path: String,
     pub upload_path: String,
     pub enable_public_registration: bool,
+    pub account_deletion_grace_period_days: i64, // 0 deletes accounts immediately
+    pub trash_retention_days: i64,               // 0 deletes files and directories immediately
+    pub default_user_quota_bytes: i64,           // 0 is unlimited
     pub allowed_cors_origins: Vec<String>,
 }
 
@@ -1,4 +1,4 @@
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::Result as IoResult;
 use std::str::FromStr;
 use std::{fs, io};
@@ -10,6 +10,7 @@ use async_recursion::async_recursion;
 use futures::channel::mpsc::Receiver;
 use mime::Mime;
 use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
 use tracing::error;
 
 use crate::archive::{ArchiveMethod, FileWithPath};
@@ -41,10 +42,64 @@ impl StorageProvider {
             .await?
             .map_err(|e| actix_web::error::ErrorInternalServerError(e))
     }
+    /// Opens an existing blob to append the next part of a resumable upload.
+    pub async fn open_file_for_append(uuid: String) -> actix_web::Result<File> {
+        web::block(move || {
+            OpenOptions::new()
+                .append(true)
+                .open(StorageProvider::get_direct_file_path(uuid))
+        })
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)
+    }
+    /// Cuts the blob back to the given length, e.g. to discard a part with a wrong checksum.
+    pub async fn truncate_file(uuid: String, length: u64) -> actix_web::Result<()> {
+        web::block(move || {
+            OpenOptions::new()
+                .write(true)
+                .open(StorageProvider::get_direct_file_path(uuid))?
+                .set_len(length)
+        })
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)
+    }
+    /// Returns the hex encoded sha256 of the blob.
+    pub async fn get_file_sha256(uuid: String) -> actix_web::Result<String> {
+        let hash = web::block(move || {
+            let mut hasher = Sha256::new();
+            io::copy(
+                &mut File::open(StorageProvider::get_direct_file_path(uuid))?,
+                &mut hasher,
+            )?;
+            Ok::<_, io::Error>(hasher.finalize())
+        })
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        Ok(hash.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+    /// Duplicates the blob, filesystems with reflinks share the data until one of the copies changes.
+    pub async fn copy_file(uuid: String, new_uuid: String) -> actix_web::Result<()> {
+        web::block(move || {
+            fs::copy(
+                StorageProvider::get_direct_file_path(uuid),
+                StorageProvider::get_direct_file_path(new_uuid),
+            )
+        })
+        .await?
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+        Ok(())
+    }
     pub fn delete_file(uuid: String) -> std::io::Result<()> {
         fs::remove_file(StorageProvider::get_direct_file_path(uuid))?;
         Ok(())
     }
+    /// Returns the size of the stored blob, 0 if it does not exist (e.g. an unfinished upload).
+    pub fn get_file_size(uuid: String) -> u64 {
+        fs::metadata(StorageProvider::get_direct_file_path(uuid))
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
     pub fn get_named_file(file: &DBFile) -> actix_web::Result<NamedFile> {
         let mut named_file = NamedFile::open(Self::get_direct_file_path(file.uuid.to_string()))?;
 
@@ -115,7 +170,7 @@ impl StorageProvider {
         path_prefix: String,
     ) {
         //add direct files in dir
-        for db_file in dir.get_files().await {
+        for db_file in dir.get_finished_files().await {
             if let Ok(file) = File::open(Self::get_direct_file_path(db_file.uuid.to_string())) {
                 files.push(FileWithPath {
                     file,
@@ -0,0 +1,167 @@
+//! Throttling of failed logins and registrations per account and per client ip.
+//! The failures are stored in MongoDB, so all workers and instances share the lockouts.
+use actix_web::HttpRequest;
+use mongodb::bson::DateTime;
+use tracing::{event, Level};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::login_throttle_dao::LoginThrottleDAO;
+use crate::database::entities::login_throttle::{LoginThrottle, LoginThrottleKind};
+use crate::SETTINGS;
+
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Returns the ip of the client, the `X-Forwarded-For` header is only used behind a trusted proxy.
+pub fn get_client_ip(request: &HttpRequest) -> String {
+    let connection = request.connection_info().clone();
+    let forwarded_for = request
+        .headers()
+        .get(FORWARDED_FOR_HEADER)
+        .and_then(|header| header.to_str().ok());
+
+    resolve_client_ip(
+        connection.peer_addr(),
+        forwarded_for,
+        &SETTINGS.get().unwrap().server.trusted_proxies,
+    )
+}
+
+fn resolve_client_ip(
+    peer_addr: Option<&str>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[String],
+) -> String {
+    let peer_addr = peer_addr.unwrap_or("unknown host");
+    if !trusted_proxies.iter().any(|proxy| proxy == peer_addr) {
+        return peer_addr.to_string();
+    }
+
+    // every proxy appends the address it got the request from, so the last untrusted one is the client
+    let forwarded_for: Vec<&str> = forwarded_for
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .collect();
+    forwarded_for
+        .iter()
+        .rev()
+        .find(|addr| !trusted_proxies.iter().any(|proxy| proxy == *addr))
+        .or_else(|| forwarded_for.first())
+        .unwrap_or(&peer_addr)
+        .to_string()
+}
+
+fn get_keys(email: Option<&str>, client_ip: &str) -> Vec<(LoginThrottleKind, String)> {
+    let mut keys = vec![(LoginThrottleKind::Ip, client_ip.to_string())];
+    if let Some(email) = email {
+        keys.push((LoginThrottleKind::Account, email.trim().to_lowercase()));
+    }
+    keys
+}
+
+/// Rejects the request with 429, if the account or the client ip is locked at the moment.
+pub async fn ensure_not_locked(email: Option<&str>, client_ip: &str) -> actix_web::Result<()> {
+    for (kind, key) in get_keys(email, client_ip) {
+        if let Some(login_throttle) = LoginThrottleDAO::get_by_key(kind, key.as_str()).await? {
+            match login_throttle.locked_until {
+                Some(locked_until) if locked_until > DateTime::now() => {
+                    let seconds = (locked_until.timestamp_millis()
+                        - DateTime::now().timestamp_millis())
+                        / 1000
+                        + 1;
+                    return Err(actix_web::error::ErrorTooManyRequests(format!(
+                        "Too many failed attempts, try again in {} seconds",
+                        seconds
+                    )));
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Counts a failed attempt for the account and the client ip and locks them, once there are too many.
+pub async fn register_failure(email: Option<&str>, client_ip: &str) -> actix_web::Result<()> {
+    let settings = &SETTINGS.get().unwrap().throttling;
+    let ttl = time::Duration::minutes(settings.failed_attempts_ttl_minutes);
+
+    for (kind, key) in get_keys(email, client_ip) {
+        let mut login_throttle =
+            LoginThrottleDAO::register_failure(kind, key.as_str(), ttl).await?;
+        let max_failed_attempts = match kind {
+            LoginThrottleKind::Account => settings.account_max_failed_attempts,
+            LoginThrottleKind::Ip => settings.ip_max_failed_attempts,
+        };
+
+        if let Some(lockout) = LoginThrottle::get_lockout_duration(
+            login_throttle.failed_attempts,
+            max_failed_attempts,
+            settings.base_lockout_seconds,
+            settings.max_lockout_seconds,
+        ) {
+            let locked_until = DateTime::from_millis(
+                DateTime::now().timestamp_millis() + lockout.whole_milliseconds() as i64,
+            );
+            login_throttle.locked_until = Some(locked_until);
+            // keep the failures at least as long as the lockout lasts
+            login_throttle.expiration_date = login_throttle.expiration_date.max(locked_until);
+            LoginThrottleDAO::update(&login_throttle).await?;
+
+            event!(
+                Level::WARN,
+                "locked {} {} for {} seconds after {} failed attempts",
+                kind.as_ref(),
+                key,
+                lockout.whole_seconds(),
+                login_throttle.failed_attempts
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Forgets the failed attempts of the account after a successful login.
+/// The failures of the ip are kept, otherwise an attacker could reset them with an own account.
+pub async fn register_success(email: &str) -> actix_web::Result<()> {
+    LoginThrottleDAO::delete_by_key(
+        LoginThrottleKind::Account,
+        email.trim().to_lowercase().as_str(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let trusted_proxies = vec!["10.0.0.1".to_string(), "10.0.0.2".to_string()];
+
+        // the header of untrusted peers is ignored
+        assert_eq!(
+            resolve_client_ip(Some("1.2.3.4"), Some("5.6.7.8"), &trusted_proxies),
+            "1.2.3.4"
+        );
+        assert_eq!(
+            resolve_client_ip(
+                Some("10.0.0.1"),
+                Some("5.6.7.8, 10.0.0.2"),
+                &trusted_proxies
+            ),
+            "5.6.7.8"
+        );
+        // a client can prepend arbitrary addresses, only the entry of the last proxy counts
+        assert_eq!(
+            resolve_client_ip(Some("10.0.0.1"), Some("9.9.9.9, 5.6.7.8"), &trusted_proxies),
+            "5.6.7.8"
+        );
+        assert_eq!(
+            resolve_client_ip(Some("10.0.0.1"), None, &trusted_proxies),
+            "10.0.0.1"
+        );
+    }
+}
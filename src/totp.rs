@@ -0,0 +1,116 @@
+//! Time-based one-time passwords (RFC 6238) as used by common authenticator apps.
+use base32::Alphabet;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+const TOTP_STEP_SECONDS: u64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const TOTP_SECRET_BYTES: usize = 20;
+// accept codes of the previous and the next time step to compensate clock drift
+const TOTP_ALLOWED_STEP_DRIFT: u64 = 1;
+const BASE32_ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+/// Returns a new random, base32 encoded secret.
+pub fn generate_secret() -> String {
+    let mut randoms: [u8; TOTP_SECRET_BYTES] = [0; TOTP_SECRET_BYTES];
+    SystemRandom::new()
+        .fill(&mut randoms)
+        .expect("failed to create random bytes for the totp secret");
+
+    base32::encode(BASE32_ALPHABET, &randoms)
+}
+
+/// Returns one-time recovery codes like `3f9a1-0c7be`, that can be used if the authenticator is lost.
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    let sr = SystemRandom::new();
+    (0..count)
+        .map(|_| {
+            let mut randoms: [u8; 5] = [0; 5];
+            sr.fill(&mut randoms)
+                .expect("failed to create random bytes for a recovery code");
+            let code: String = randoms.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+/// Returns the `otpauth://` uri that can be scanned (as qr code) by authenticator apps.
+pub fn get_provisioning_uri(secret: &str, account_name: &str, issuer: &str) -> String {
+    let issuer = utf8_percent_encode(issuer, NON_ALPHANUMERIC).to_string();
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        issuer,
+        utf8_percent_encode(account_name, NON_ALPHANUMERIC),
+        secret,
+        issuer,
+        TOTP_DIGITS,
+        TOTP_STEP_SECONDS
+    )
+}
+
+fn get_code(secret: &[u8], step: u64) -> u32 {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+
+    // dynamic truncation, see RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    binary % 10_u32.pow(TOTP_DIGITS)
+}
+
+/// Checks the code for the given unix time and returns the matching time step.
+/// Codes of steps up to `last_used_step` are rejected, so a code can only be used once.
+pub fn verify(
+    secret: &str,
+    code: &str,
+    unix_time: u64,
+    last_used_step: Option<u64>,
+) -> Option<u64> {
+    let secret = base32::decode(BASE32_ALPHABET, secret)?;
+    let code = code.trim().parse::<u32>().ok()?;
+    let current_step = unix_time / TOTP_STEP_SECONDS;
+
+    (current_step.saturating_sub(TOTP_ALLOWED_STEP_DRIFT)..=current_step + TOTP_ALLOWED_STEP_DRIFT)
+        // None is less than any step, so every step is allowed if no code has been used yet
+        .filter(|step| last_used_step < Some(*step))
+        .find(|step| get_code(secret.as_slice(), *step) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B test secret for SHA1
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc_6238_vectors() {
+        assert_eq!(get_code(RFC_SECRET, 59 / TOTP_STEP_SECONDS), 287082);
+        assert_eq!(get_code(RFC_SECRET, 1111111109 / TOTP_STEP_SECONDS), 81804);
+        assert_eq!(get_code(RFC_SECRET, 1234567890 / TOTP_STEP_SECONDS), 5924);
+        assert_eq!(get_code(RFC_SECRET, 2000000000 / TOTP_STEP_SECONDS), 279037);
+    }
+
+    #[test]
+    fn test_verify() {
+        let secret = base32::encode(BASE32_ALPHABET, RFC_SECRET);
+        let step = 1111111109 / TOTP_STEP_SECONDS;
+
+        assert_eq!(verify(&secret, "081804", 1111111109, None), Some(step));
+        // clock drift of one step
+        assert_eq!(verify(&secret, "081804", 1111111109 + 30, None), Some(step));
+        assert_eq!(verify(&secret, "081804", 1111111109 + 90, None), None);
+        // replay of an already used code
+        assert_eq!(verify(&secret, "081804", 1111111109, Some(step)), None);
+        assert_eq!(verify(&secret, "123456", 1111111109, None), None);
+        assert_eq!(verify(&secret, "not a code", 1111111109, None), None);
+    }
+}
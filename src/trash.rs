@@ -0,0 +1,225 @@
+//! Trash bin for deleted files and directories, they are purged after the retention period.
+//! A trashed directory flags its whole subtree, so the subtree is restored or purged together.
+use std::time::Duration;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use tracing::{event, Level};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::directory_dao::DirectoryDAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::daos::syncstate_dao::SyncStateDAO;
+use crate::database::entities::directory::Directory;
+use crate::database::entities::file::File;
+use crate::database::entities::syncstate::{SyncState, SyncStateAction, SyncStateType};
+use crate::quota;
+use crate::SETTINGS;
+
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn is_enabled() -> bool {
+    SETTINGS.get().unwrap().trash_retention_days > 0
+}
+
+/// Moves the file to the trash, or deletes it permanently if there is no retention period.
+pub async fn trash_file(file: &File) -> actix_web::Result<()> {
+    if !is_enabled() {
+        return delete_file(file).await;
+    }
+
+    let id = file
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("file id not found"))?;
+    FileDAO::set_deletion_date(id, Some(DateTime::now()), None).await?;
+
+    let _ = SyncStateDAO::insert(&mut SyncState::new(
+        SyncStateType::File,
+        SyncStateAction::Delete,
+        id,
+        Some(file.parent_id),
+        file.user_id,
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Moves the directory with its subtree to the trash, or deletes it permanently if there is no
+/// retention period. Children, that are already in the trash, keep their own trash entry.
+pub async fn trash_directory(dir: &Directory) -> actix_web::Result<()> {
+    if dir.parent_id.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The root directory cannot be deleted",
+        ));
+    }
+
+    let id = dir
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("directory id not found"))?;
+    let deletion_date = DateTime::now();
+    DirectoryDAO::set_deletion_date(id, Some(deletion_date), None).await?;
+
+    if !is_enabled() {
+        // the directory stays hidden in the trash, if the deletion gets interrupted
+        return delete_directory(dir).await;
+    }
+
+    let mut pending_dir_ids = vec![id];
+    while let Some(dir_id) = pending_dir_ids.pop() {
+        FileDAO::trash_by_parent_id(dir_id, deletion_date, id).await?;
+        for child in DirectoryDAO::get_all_with_parent_id(Some(dir_id)).await? {
+            if let Some(child_id) = child.id {
+                DirectoryDAO::set_deletion_date(child_id, Some(deletion_date), Some(id)).await?;
+                pending_dir_ids.push(child_id);
+            }
+        }
+    }
+
+    let _ = SyncStateDAO::insert(&mut SyncState::new(
+        SyncStateType::Directory,
+        SyncStateAction::Delete,
+        id,
+        dir.parent_id,
+        dir.user_id,
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Returns the directory an item is restored to, the root directory if the original one is gone.
+async fn get_restore_target(
+    parent_id: Option<ObjectId>,
+    user_id: ObjectId,
+    root_dir_id: ObjectId,
+) -> actix_web::Result<Directory> {
+    if let Some(parent_id) = parent_id {
+        if let Some(parent) = DirectoryDAO::get_with_user(parent_id, user_id).await? {
+            return Ok(parent);
+        }
+    }
+
+    DirectoryDAO::get_with_user(root_dir_id, user_id)
+        .await?
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("root directory not found"))
+}
+
+/// Restores the file into its original directory, a name conflict is solved with "name (1).ext".
+pub async fn restore_file(mut file: File, root_dir_id: ObjectId) -> actix_web::Result<File> {
+    let id = file
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("file id not found"))?;
+    let parent = get_restore_target(Some(file.parent_id), file.user_id, root_dir_id).await?;
+
+    file.parent_id = parent.id.unwrap();
+    file.name = parent.get_free_file_name(&file.name).await;
+    file.deletion_date = None;
+    FileDAO::update(&file).await?;
+    FileDAO::set_deletion_date(id, None, None).await?;
+
+    FileDAO::insert_create_sync_state(&file).await?;
+    Ok(file)
+}
+
+/// Restores the directory with its subtree, a name conflict is solved with "name (1)".
+pub async fn restore_directory(
+    mut dir: Directory,
+    root_dir_id: ObjectId,
+) -> actix_web::Result<Directory> {
+    let id = dir
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("directory id not found"))?;
+    let parent = get_restore_target(dir.parent_id, dir.user_id, root_dir_id).await?;
+    let parent_id = parent.id.unwrap();
+
+    dir.name = DirectoryDAO::get_free_name(&dir.name, parent_id).await?;
+
+    if dir.parent_id == Some(parent_id) {
+        DirectoryDAO::update(&dir).await?;
+    } else {
+        DirectoryDAO::set_parent(&mut dir, parent_id).await?;
+    }
+    DirectoryDAO::set_deletion_date(id, None, None).await?;
+    DirectoryDAO::restore_trashed_with(id).await?;
+    FileDAO::restore_trashed_with(id).await?;
+    dir.deletion_date = None;
+
+    let _ = SyncStateDAO::insert(&mut SyncState::new(
+        SyncStateType::Directory,
+        SyncStateAction::Create,
+        id,
+        dir.parent_id,
+        dir.user_id,
+    ))
+    .await?;
+    Ok(dir)
+}
+
+/// Deletes the file and its blob permanently.
+pub async fn delete_file(file: &File) -> actix_web::Result<()> {
+    quota::delete_file_blob(file).await?;
+    FileDAO::delete(file).await?;
+    Ok(())
+}
+
+/// Deletes the directory with its whole subtree permanently.
+pub async fn delete_directory(dir: &Directory) -> actix_web::Result<()> {
+    DirectoryDAO::delete(dir).await?;
+    Ok(())
+}
+
+/// Deletes everything in the trash of the user permanently.
+pub async fn empty(user_id: ObjectId) -> actix_web::Result<()> {
+    for file in FileDAO::get_trashed_for_user(user_id).await? {
+        delete_file(&file).await?;
+    }
+    for dir in DirectoryDAO::get_trashed_for_user(user_id).await? {
+        delete_directory(&dir).await?;
+    }
+    Ok(())
+}
+
+async fn purge_expired(oldest_deletion_date: DateTime) -> actix_web::Result<()> {
+    for file in FileDAO::get_trashed_before(oldest_deletion_date).await? {
+        if let Err(e) = delete_file(&file).await {
+            event!(
+                Level::WARN,
+                "Failed to purge file {} from the trash: {}",
+                file.uuid,
+                e
+            );
+        }
+    }
+    for dir in DirectoryDAO::get_trashed_before(oldest_deletion_date).await? {
+        if let Err(e) = delete_directory(&dir).await {
+            event!(
+                Level::WARN,
+                "Failed to purge directory {:?} from the trash: {}",
+                dir.id,
+                e
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Periodically deletes the items, that are longer in the trash than the retention period.
+pub fn spawn_trash_purge() {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(TRASH_PURGE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            // without a retention period only interrupted deletions are left in the trash
+            let retention_days = SETTINGS.get().unwrap().trash_retention_days.max(0);
+            let oldest_deletion_date = DateTime::from_millis(
+                DateTime::now().timestamp_millis()
+                    - time::Duration::days(retention_days).whole_milliseconds() as i64,
+            );
+
+            if let Err(e) = purge_expired(oldest_deletion_date).await {
+                event!(Level::WARN, "Failed to purge the trash: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,306 @@
+//! Helpers for the tus resumable upload protocol, see https://tus.io/protocols/resumable-upload.
+//! Supported are the core protocol and the creation, expiration, checksum and termination extensions.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use mongodb::bson::{DateTime, Uuid};
+use ring::digest;
+use tracing::{event, Level};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::entities::file::File;
+use crate::quota;
+
+pub const TUS_VERSION: &str = "1.0.0";
+pub const TUS_EXTENSIONS: &str = "creation,expiration,checksum,termination";
+pub const TUS_CHECKSUM_ALGORITHMS: &str = "sha1,sha256";
+pub const TUS_OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+pub const TUS_RESUMABLE_HEADER: &str = "Tus-Resumable";
+pub const TUS_VERSION_HEADER: &str = "Tus-Version";
+pub const TUS_EXTENSION_HEADER: &str = "Tus-Extension";
+pub const TUS_CHECKSUM_ALGORITHM_HEADER: &str = "Tus-Checksum-Algorithm";
+pub const UPLOAD_LENGTH_HEADER: &str = "Upload-Length";
+pub const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+pub const UPLOAD_METADATA_HEADER: &str = "Upload-Metadata";
+pub const UPLOAD_EXPIRES_HEADER: &str = "Upload-Expires";
+pub const UPLOAD_CHECKSUM_HEADER: &str = "Upload-Checksum";
+
+// not a registered status code, defined by the checksum extension
+const CHECKSUM_MISMATCH_STATUS: u16 = 460;
+const UPLOAD_TTL_HOURS: i64 = 24;
+const EXPIRED_UPLOADS_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const UPLOAD_LOCK_TTL_MINUTES: i64 = 10;
+// the holder only writes within this time after a renewal, so it never writes with an expired lock
+const UPLOAD_LOCK_RENEW_INTERVAL_MINUTES: i64 = 1;
+
+/// Marks an upload as receiving data in the database, so parallel requests cannot write to the
+/// same blob, even if they are handled by different instances. The lock expires, if its holder
+/// stops renewing it, e.g. because the instance crashed.
+pub struct UploadLock {
+    uuid: String,
+    lock: String,
+    renewal_date: DateTime,
+    released: bool,
+}
+
+impl UploadLock {
+    /// Locks the unfinished upload, as long as it has still received `file.size` bytes.
+    pub async fn acquire(file: &File) -> actix_web::Result<UploadLock> {
+        let lock = Uuid::new().to_string();
+        let renewal_date = DateTime::now();
+        if !FileDAO::lock_upload(
+            file.uuid.as_str(),
+            file.size,
+            lock.as_str(),
+            get_lock_expiration_date(renewal_date),
+        )
+        .await?
+        {
+            return Err(get_error(
+                StatusCode::LOCKED,
+                "The upload is receiving data in another request",
+            ));
+        }
+
+        Ok(UploadLock {
+            uuid: file.uuid.clone(),
+            lock,
+            renewal_date,
+            released: false,
+        })
+    }
+
+    /// Extends the lock while data is received, fails if the lock has been lost in the meantime.
+    pub async fn renew_if_due(&mut self) -> actix_web::Result<()> {
+        let now = DateTime::now();
+        if now.timestamp_millis() - self.renewal_date.timestamp_millis()
+            < time::Duration::minutes(UPLOAD_LOCK_RENEW_INTERVAL_MINUTES).whole_milliseconds()
+                as i64
+        {
+            return Ok(());
+        }
+
+        if !FileDAO::renew_upload_lock(
+            self.uuid.as_str(),
+            self.lock.as_str(),
+            get_lock_expiration_date(now),
+        )
+        .await?
+        {
+            return Err(get_error(
+                StatusCode::LOCKED,
+                "The upload is receiving data in another request",
+            ));
+        }
+        self.renewal_date = now;
+        Ok(())
+    }
+
+    pub async fn release(mut self) -> actix_web::Result<()> {
+        self.released = true;
+        FileDAO::unlock_upload(self.uuid.as_str(), self.lock.as_str()).await
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+
+        // e.g. after an error, otherwise the upload stays locked until the lock expires
+        let uuid = self.uuid.clone();
+        let lock = self.lock.clone();
+        actix_web::rt::spawn(async move {
+            if let Err(e) = FileDAO::unlock_upload(uuid.as_str(), lock.as_str()).await {
+                event!(Level::WARN, "Failed to unlock upload {}: {}", uuid, e);
+            }
+        });
+    }
+}
+
+fn get_lock_expiration_date(date: DateTime) -> DateTime {
+    DateTime::from_millis(
+        date.timestamp_millis()
+            + time::Duration::minutes(UPLOAD_LOCK_TTL_MINUTES).whole_milliseconds() as i64,
+    )
+}
+
+/// Returns an error response, that also carries the `Tus-Resumable` header.
+pub fn get_error(status: StatusCode, message: &str) -> actix_web::Error {
+    actix_web::error::InternalError::from_response(
+        message.to_string(),
+        HttpResponse::build(status)
+            .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+            .body(message.to_string()),
+    )
+    .into()
+}
+
+pub fn get_checksum_mismatch_error() -> actix_web::Error {
+    get_error(
+        StatusCode::from_u16(CHECKSUM_MISMATCH_STATUS).unwrap(),
+        "Checksum mismatch, the part has been discarded",
+    )
+}
+
+/// Rejects requests of clients, that speak another protocol version.
+pub fn ensure_tus_resumable(request: &HttpRequest) -> actix_web::Result<()> {
+    match get_header(request, TUS_RESUMABLE_HEADER) {
+        Some(TUS_VERSION) => Ok(()),
+        _ => Err(actix_web::error::InternalError::from_response(
+            "Unsupported tus version",
+            HttpResponse::PreconditionFailed()
+                .insert_header((TUS_VERSION_HEADER, TUS_VERSION))
+                .finish(),
+        )
+        .into()),
+    }
+}
+
+pub fn get_header<'a>(request: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+}
+
+/// Parses a non-negative number header like `Upload-Length` or `Upload-Offset`.
+pub fn get_length_header(request: &HttpRequest, name: &str) -> actix_web::Result<i64> {
+    get_header(request, name)
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|value| *value >= 0)
+        .ok_or_else(|| {
+            get_error(
+                StatusCode::BAD_REQUEST,
+                format!("Header {} is missing or invalid", name).as_str(),
+            )
+        })
+}
+
+/// Parses `Upload-Metadata`, comma separated pairs of a key and an optional base64 encoded value.
+pub fn parse_metadata(header: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+
+    for pair in header
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let mut parts = pair.split(' ');
+        let key = parts.next()?;
+        let value = match parts.next() {
+            Some(value) => String::from_utf8(base64::decode(value).ok()?).ok()?,
+            None => String::new(),
+        };
+        if parts.next().is_some() {
+            return None;
+        }
+        metadata.insert(key.to_string(), value);
+    }
+
+    Some(metadata)
+}
+
+/// Parses `Upload-Checksum` like `sha1 <base64 digest>` into the algorithm and the expected digest.
+pub fn parse_checksum(header: &str) -> Option<(&'static digest::Algorithm, Vec<u8>)> {
+    let (algorithm, checksum) = header.trim().split_once(' ')?;
+    let algorithm = match algorithm {
+        "sha1" => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        "sha256" => &digest::SHA256,
+        _ => return None,
+    };
+    Some((algorithm, base64::decode(checksum.trim()).ok()?))
+}
+
+pub fn get_expiration_date() -> DateTime {
+    DateTime::from_millis(
+        DateTime::now().timestamp_millis()
+            + time::Duration::hours(UPLOAD_TTL_HOURS).whole_milliseconds() as i64,
+    )
+}
+
+/// Formats a date for the `Upload-Expires` header (RFC 7231).
+pub fn format_expiration_date(date: DateTime) -> String {
+    actix_web::http::header::HttpDate::from(date.to_system_time()).to_string()
+}
+
+/// Periodically removes the resumable uploads, that have not been finished in time.
+/// Uploads, that are receiving data at the moment, are left alone.
+pub fn spawn_expired_upload_cleanup() {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(EXPIRED_UPLOADS_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match FileDAO::get_expired_uploads().await {
+                Ok(files) => {
+                    for file in files {
+                        let lock = match UploadLock::acquire(&file).await {
+                            Ok(lock) => lock,
+                            Err(_) => continue,
+                        };
+
+                        if let Err(e) = quota::delete_file_blob(&file).await {
+                            event!(
+                                Level::WARN,
+                                "Failed to delete expired upload {}: {}",
+                                file.uuid,
+                                e
+                            );
+                            continue;
+                        }
+                        if let Err(e) = FileDAO::delete(&file).await {
+                            event!(
+                                Level::WARN,
+                                "Failed to delete expired upload {}: {}",
+                                file.uuid,
+                                e
+                            );
+                        }
+                        let _ = lock.release().await;
+                    }
+                }
+                Err(e) => event!(Level::WARN, "Failed to get expired uploads: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata =
+            parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==,is_confidential")
+                .unwrap();
+        assert_eq!(
+            metadata.get("filename").map(String::as_str),
+            Some("world_domination_plan.pdf")
+        );
+        assert_eq!(
+            metadata.get("is_confidential").map(String::as_str),
+            Some("")
+        );
+
+        assert!(parse_metadata("").unwrap().is_empty());
+        assert!(parse_metadata("filename not-base64!").is_none());
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let (algorithm, checksum) = parse_checksum("sha1 Kq5sNclPz7QV2+lfQIuc6R7oRu0=").unwrap();
+        assert_eq!(algorithm, &digest::SHA1_FOR_LEGACY_USE_ONLY);
+        assert_eq!(checksum.len(), 20);
+
+        assert!(parse_checksum("md5 Kq5sNclPz7QV2+lfQIuc6R7oRu0=").is_none());
+        assert!(parse_checksum("sha1").is_none());
+    }
+}
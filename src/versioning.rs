@@ -0,0 +1,208 @@
+//! Previous contents of files, that are kept as versions when the content gets replaced.
+//! The versions are removed again once there are too many of them or they are too old.
+use std::time::Duration;
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use tracing::{event, Level};
+
+use crate::database::daos::dao::DAO;
+use crate::database::daos::file_dao::FileDAO;
+use crate::database::daos::file_version_dao::FileVersionDAO;
+use crate::database::daos::syncstate_dao::SyncStateDAO;
+use crate::database::entities::file::File;
+use crate::database::entities::file_version::FileVersion;
+use crate::database::entities::syncstate::{SyncState, SyncStateAction, SyncStateType};
+use crate::quota;
+use crate::SETTINGS;
+
+const EXPIRED_VERSIONS_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Keeps the blob of the file as a version, before the file gets pointed to another blob.
+pub async fn keep_version(previous_file: &File) -> actix_web::Result<()> {
+    let file_id = previous_file
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("file id not found"))?;
+
+    FileVersionDAO::insert(&mut FileVersion {
+        id: None,
+        file_id,
+        user_id: previous_file.user_id,
+        uuid: previous_file.uuid.to_owned(),
+        hash: previous_file.hash.to_owned(),
+        size: previous_file.size,
+        mime: previous_file.mime.to_owned(),
+        creation_date: previous_file.creation_date,
+        modification_date: previous_file.modification_date,
+        replaced_date: DateTime::now(),
+    })
+    .await?;
+
+    apply_retention(file_id).await
+}
+
+/// Deletes the versions of the file, that exceed the configured retention.
+pub async fn apply_retention(file_id: ObjectId) -> actix_web::Result<()> {
+    let settings = &SETTINGS.get().unwrap().versioning;
+    let file_versions = FileVersionDAO::get_all_for_file(file_id).await?;
+
+    for file_version in get_expired_versions(
+        file_versions,
+        settings.max_versions_per_file,
+        settings.max_version_age_days,
+        DateTime::now(),
+    ) {
+        delete_version(&file_version).await?;
+    }
+    Ok(())
+}
+
+/// Returns the versions, that have to be deleted. The versions have to be sorted with the most
+/// recently replaced first, limits of 0 are unlimited.
+fn get_expired_versions(
+    file_versions: Vec<FileVersion>,
+    max_versions: u32,
+    max_age_days: i64,
+    now: DateTime,
+) -> Vec<FileVersion> {
+    let oldest_replaced_date = DateTime::from_millis(
+        now.timestamp_millis() - time::Duration::days(max_age_days).whole_milliseconds() as i64,
+    );
+
+    file_versions
+        .into_iter()
+        .enumerate()
+        .filter(|(index, file_version)| {
+            (max_versions > 0 && *index >= max_versions as usize)
+                || (max_age_days > 0 && file_version.replaced_date < oldest_replaced_date)
+        })
+        .map(|(_, file_version)| file_version)
+        .collect()
+}
+
+/// Deletes the version and its blob, the bytes are given back to the quota of the owner.
+pub async fn delete_version(file_version: &FileVersion) -> actix_web::Result<()> {
+    quota::delete_version_blob(file_version).await?;
+    FileVersionDAO::delete(file_version).await?;
+    Ok(())
+}
+
+/// Makes the version the current content of the file, the replaced content is kept as a version.
+pub async fn restore(mut file: File, file_version: &FileVersion) -> actix_web::Result<File> {
+    let previous_file = file.clone();
+    file.uuid = file_version.uuid.to_owned();
+    file.hash = file_version.hash.to_owned();
+    file.size = file_version.size;
+    file.mime = file_version.mime.to_owned();
+    file.modification_date = file_version.modification_date;
+    FileDAO::update(&file).await?;
+    FileVersionDAO::delete(file_version).await?;
+
+    keep_version(&previous_file).await?;
+    insert_update_sync_state(&file).await?;
+    Ok(file)
+}
+
+pub async fn insert_update_sync_state(file: &File) -> actix_web::Result<()> {
+    let id = file
+        .id
+        .ok_or_else(|| actix_web::error::ErrorInternalServerError("file id not found"))?;
+
+    let _ = SyncStateDAO::insert(&mut SyncState::new(
+        SyncStateType::File,
+        SyncStateAction::Update,
+        id,
+        Some(file.parent_id),
+        file.user_id,
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Periodically removes the versions of all users, that are older than the configured age.
+pub fn spawn_expired_version_cleanup() {
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(EXPIRED_VERSIONS_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let max_age_days = SETTINGS.get().unwrap().versioning.max_version_age_days;
+            if max_age_days <= 0 {
+                continue;
+            }
+            let oldest_replaced_date = DateTime::from_millis(
+                DateTime::now().timestamp_millis()
+                    - time::Duration::days(max_age_days).whole_milliseconds() as i64,
+            );
+
+            match FileVersionDAO::get_replaced_before(oldest_replaced_date).await {
+                Ok(file_versions) => {
+                    for file_version in file_versions {
+                        if let Err(e) = delete_version(&file_version).await {
+                            event!(
+                                Level::WARN,
+                                "Failed to delete expired file version {}: {}",
+                                file_version.uuid,
+                                e
+                            );
+                        }
+                    }
+                }
+                Err(e) => event!(Level::WARN, "Failed to get expired file versions: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_file_version(replaced_days_ago: i64, now: DateTime) -> FileVersion {
+        FileVersion {
+            id: None,
+            file_id: ObjectId::new(),
+            user_id: ObjectId::new(),
+            uuid: format!("version-{}", replaced_days_ago),
+            hash: "".to_string(),
+            size: 0,
+            mime: "text/plain".to_string(),
+            creation_date: now,
+            modification_date: None,
+            replaced_date: DateTime::from_millis(
+                now.timestamp_millis()
+                    - time::Duration::days(replaced_days_ago).whole_milliseconds() as i64,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_get_expired_versions() {
+        let now = DateTime::now();
+        let file_versions: Vec<FileVersion> = [1, 2, 5, 40]
+            .iter()
+            .map(|days| get_file_version(*days, now))
+            .collect();
+        let get_uuids = |file_versions: Vec<FileVersion>| -> Vec<String> {
+            file_versions
+                .into_iter()
+                .map(|file_version| file_version.uuid)
+                .collect()
+        };
+
+        assert!(get_expired_versions(file_versions.clone(), 0, 0, now).is_empty());
+        assert_eq!(
+            get_uuids(get_expired_versions(file_versions.clone(), 2, 0, now)),
+            vec!["version-5", "version-40"]
+        );
+        assert_eq!(
+            get_uuids(get_expired_versions(file_versions.clone(), 0, 30, now)),
+            vec!["version-40"]
+        );
+        assert_eq!(
+            get_uuids(get_expired_versions(file_versions, 3, 3, now)),
+            vec!["version-5", "version-40"]
+        );
+    }
+}